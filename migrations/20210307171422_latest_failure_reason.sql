@@ -0,0 +1,2 @@
+ALTER TABLE services
+	ADD COLUMN latest_failure_reason VARCHAR;
@@ -0,0 +1,14 @@
+CREATE TABLE check_results (
+	id SERIAL PRIMARY KEY,
+	round INTEGER NOT NULL,
+	checked_at TIMESTAMPTZ NOT NULL,
+	svc_id VARCHAR NOT NULL,
+	vm_id VARCHAR NOT NULL,
+	team_id VARCHAR NOT NULL,
+	status VARCHAR NOT NULL,
+	latency_ms INTEGER NOT NULL,
+	error VARCHAR
+);
+
+CREATE INDEX check_results_svc_idx
+	ON check_results (team_id, vm_id, svc_id, checked_at);
@@ -0,0 +1,2 @@
+ALTER TABLE check_results
+	ADD COLUMN username VARCHAR;
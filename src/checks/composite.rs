@@ -0,0 +1,179 @@
+use super::Service;
+use crate::config::CompositeMode;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::time::Duration;
+use tokio::time;
+
+#[derive(Debug)]
+pub struct CompositeCheck {
+	// how many children have to be up
+	required: usize,
+	// keyed by the child's service id
+	children: Vec<(String, Box<dyn Service>)>,
+	// each child's own deadline, shorter than the poll's so one hanging child
+	// is reported as such instead of timing out the whole composite
+	timeout: Duration,
+}
+
+impl CompositeCheck {
+	pub fn new(
+		mode: CompositeMode,
+		children: Vec<(String, Box<dyn Service>)>,
+		timeout: Duration,
+	) -> Result<Self> {
+		let required = match mode {
+			CompositeMode::All => children.len(),
+			CompositeMode::Any => 1,
+			CompositeMode::AtLeast(n) => n,
+		};
+		if required == 0 || required > children.len() {
+			bail!(
+				"Composite needs {} of its {} checks up, which can never be \
+				 met or never fail",
+				required,
+				children.len()
+			)
+		}
+
+		Ok(Self {
+			required,
+			children,
+			timeout,
+		})
+	}
+}
+
+#[async_trait]
+impl Service for CompositeCheck {
+	async fn is_up(&self) -> Result<()> {
+		let mut pending = self
+			.children
+			.iter()
+			.map(|(id, child)| async move {
+				match time::timeout(self.timeout, child.is_up()).await {
+					Ok(Ok(())) => Ok(()),
+					Ok(Err(e)) => Err(format!("{} ({:#})", id, e)),
+					Err(_) => Err(format!("{} (timed out)", id)),
+				}
+			})
+			.collect::<FuturesUnordered<_>>();
+
+		// stops as soon as the outcome is settled, whatever is still running
+		let (mut up, mut failed) = (0, Vec::new());
+		while let Some(result) = pending.next().await {
+			match result {
+				Ok(()) => up += 1,
+				Err(reason) => failed.push(reason),
+			}
+			if up >= self.required {
+				return Ok(());
+			}
+			if up + pending.len() < self.required {
+				break;
+			}
+		}
+
+		bail!(
+			"{} of {} checks up, {} needed; failed: {}",
+			up,
+			self.children.len(),
+			self.required,
+			failed.join(", ")
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::future;
+	use tokio::runtime::Runtime;
+
+	#[derive(Debug)]
+	enum Stub {
+		Up,
+		Down,
+		Hang,
+	}
+
+	#[async_trait]
+	impl Service for Stub {
+		async fn is_up(&self) -> Result<()> {
+			match self {
+				Stub::Up => Ok(()),
+				Stub::Down => bail!("down"),
+				Stub::Hang => future::pending().await,
+			}
+		}
+	}
+
+	fn composite(
+		mode: CompositeMode,
+		stubs: Vec<Stub>,
+	) -> Result<CompositeCheck> {
+		let children = stubs
+			.into_iter()
+			.enumerate()
+			.map(|(i, stub)| {
+				(format!("c{}", i), Box::new(stub) as Box<dyn Service>)
+			})
+			.collect();
+		CompositeCheck::new(mode, children, Duration::from_millis(50))
+	}
+
+	fn check(mode: CompositeMode, stubs: Vec<Stub>) -> Result<()> {
+		let check = composite(mode, stubs).unwrap();
+		Runtime::new().unwrap().block_on(check.is_up())
+	}
+
+	#[test]
+	fn all_needs_every_child() {
+		use Stub::*;
+		assert!(check(CompositeMode::All, vec![Up, Up]).is_ok());
+		let err = check(CompositeMode::All, vec![Up, Down]).unwrap_err();
+		// the up child may not have been counted before the outcome was settled
+		assert!(err.to_string().ends_with("2 needed; failed: c1 (down)"));
+	}
+
+	#[test]
+	fn any_needs_one_child() {
+		use Stub::*;
+		assert!(check(CompositeMode::Any, vec![Down, Up]).is_ok());
+		assert!(check(CompositeMode::Any, vec![Down, Down]).is_err());
+	}
+
+	#[test]
+	fn any_ignores_a_hanging_child_once_another_is_up() {
+		use Stub::*;
+		assert!(check(CompositeMode::Any, vec![Hang, Up]).is_ok());
+	}
+
+	#[test]
+	fn hanging_children_are_named() {
+		use Stub::*;
+		let err = check(CompositeMode::All, vec![Up, Hang]).unwrap_err();
+		assert!(
+			err.to_string().ends_with("failed: c1 (timed out)"),
+			"{}",
+			err
+		);
+	}
+
+	#[test]
+	fn at_least_counts_children() {
+		use Stub::*;
+		let mode = CompositeMode::AtLeast(2);
+		assert!(check(mode, vec![Up, Down, Up]).is_ok());
+		assert!(check(mode, vec![Up, Down, Down]).is_err());
+	}
+
+	#[test]
+	fn unsatisfiable_modes_are_rejected() {
+		use Stub::*;
+		assert!(composite(CompositeMode::AtLeast(0), vec![Up]).is_err());
+		assert!(composite(CompositeMode::AtLeast(3), vec![Up, Up]).is_err());
+		assert!(composite(CompositeMode::Any, vec![]).is_err());
+	}
+}
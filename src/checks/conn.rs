@@ -0,0 +1,70 @@
+use super::tls;
+use anyhow::{bail, Result};
+use std::{net::SocketAddrV4, sync::Arc};
+use tokio::{
+	io::{
+		AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+		BufReader,
+	},
+	net::TcpStream,
+};
+use tokio_rustls::TlsConnector;
+
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+// A buffered connection for the line-based protocols (SMTP, POP3, IMAP, ...)
+// that may start out in plaintext and be upgraded to TLS partway through.
+pub struct LineConn {
+	inner: BufReader<Box<dyn Stream>>,
+}
+
+impl LineConn {
+	pub async fn connect(remote: SocketAddrV4, tls: bool) -> Result<Self> {
+		let stream = TcpStream::connect(remote).await?;
+		let conn = Self {
+			inner: BufReader::new(Box::new(stream) as Box<dyn Stream>),
+		};
+		if tls {
+			conn.upgrade().await
+		} else {
+			Ok(conn)
+		}
+	}
+
+	// anything still sitting in the read buffer is dropped, which is fine
+	// since the server shouldn't send anything until the handshake
+	pub async fn upgrade(self) -> Result<Self> {
+		let stream =
+			TlsConnector::from(Arc::new(tls::insecure_client_config()))
+				.connect(tls::placeholder_domain(), self.inner.into_inner())
+				.await?;
+		Ok(Self {
+			inner: BufReader::new(Box::new(stream) as Box<dyn Stream>),
+		})
+	}
+
+	// returns the line without its terminator
+	pub async fn read_line(&mut self) -> Result<String> {
+		let mut line = String::new();
+		if self.inner.read_line(&mut line).await? == 0 {
+			bail!("Connection closed by server")
+		}
+		Ok(line.trim_end_matches(&['\r', '\n'][..]).to_owned())
+	}
+
+	pub async fn read_exact(&mut self, len: usize) -> Result<Vec<u8>> {
+		let mut buf = vec![0; len];
+		self.inner.read_exact(&mut buf).await?;
+		Ok(buf)
+	}
+
+	pub async fn write_line(&mut self, line: &str) -> Result<()> {
+		self.write_all(format!("{}\r\n", line).as_bytes()).await
+	}
+
+	pub async fn write_all(&mut self, data: &[u8]) -> Result<()> {
+		self.inner.write_all(data).await?;
+		Ok(self.inner.flush().await?)
+	}
+}
@@ -1,60 +1,273 @@
-use crate::config::DnsRecord;
+use crate::config::{DnsMatch, DnsRecordType};
 
 use super::Service;
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
-use std::net::Ipv4Addr;
+use std::{
+	net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4},
+	str::FromStr,
+};
 use trust_dns_proto::{
-	rr::{RData, RecordType},
+	rr::{Name, RData, RecordType},
 	xfer::DnsRequestOptions,
 };
-use trust_dns_resolver::AsyncResolver;
+use trust_dns_resolver::{
+	config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+	TokioAsyncResolver,
+};
+
+#[derive(Debug, Clone)]
+pub enum DnsRecord {
+	A(Ipv4Addr),
+	AAAA(Ipv6Addr),
+	MX {
+		preference: Option<u16>,
+		exchange: Name,
+	},
+	CNAME(Name),
+	TXT(String),
+	NS(Name),
+	SOA {
+		serial: u32,
+	},
+	PTR(Name),
+	SRV {
+		priority: Option<u16>,
+		weight: Option<u16>,
+		port: u16,
+		target: Name,
+	},
+}
+
+impl DnsRecord {
+	// values are written the way they'd appear in a zone file, minus the
+	// owner, class and type: `10 mail.team.local.` for MX, `0 5 5060
+	// sip.team.local.` for SRV and the bare serial for SOA. MX preference and
+	// SRV priority/weight may be left off to match any.
+	pub fn parse(ty: DnsRecordType, value: &str) -> Result<Self> {
+		let fields = value.split_whitespace().collect::<Vec<_>>();
+		let invalid = || anyhow!("Invalid {:?} record value: {}", ty, value);
+
+		Ok(match ty {
+			DnsRecordType::A => DnsRecord::A(value.trim().parse()?),
+			DnsRecordType::AAAA => DnsRecord::AAAA(value.trim().parse()?),
+			DnsRecordType::MX => match fields[..] {
+				[exchange] => DnsRecord::MX {
+					preference: None,
+					exchange: Name::from_str(exchange)?,
+				},
+				[preference, exchange] => DnsRecord::MX {
+					preference: Some(preference.parse()?),
+					exchange: Name::from_str(exchange)?,
+				},
+				_ => return Err(invalid()),
+			},
+			DnsRecordType::CNAME => {
+				DnsRecord::CNAME(Name::from_str(value.trim())?)
+			}
+			DnsRecordType::TXT => DnsRecord::TXT(value.to_owned()),
+			DnsRecordType::NS => DnsRecord::NS(Name::from_str(value.trim())?),
+			DnsRecordType::SOA => DnsRecord::SOA {
+				serial: value.trim().parse()?,
+			},
+			DnsRecordType::PTR => DnsRecord::PTR(Name::from_str(value.trim())?),
+			DnsRecordType::SRV => match fields[..] {
+				[port, target] => DnsRecord::SRV {
+					priority: None,
+					weight: None,
+					port: port.parse()?,
+					target: Name::from_str(target)?,
+				},
+				[priority, weight, port, target] => DnsRecord::SRV {
+					priority: Some(priority.parse()?),
+					weight: Some(weight.parse()?),
+					port: port.parse()?,
+					target: Name::from_str(target)?,
+				},
+				_ => return Err(invalid()),
+			},
+		})
+	}
+
+	fn matches(&self, data: &RData) -> bool {
+		match (self, data) {
+			(DnsRecord::A(addr), RData::A(inner)) => addr == inner,
+			(DnsRecord::AAAA(addr), RData::AAAA(inner)) => addr == inner,
+			(
+				DnsRecord::MX {
+					preference,
+					exchange,
+				},
+				RData::MX(inner),
+			) => {
+				exchange == inner.exchange()
+					&& preference.map_or(true, |p| p == inner.preference())
+			}
+			(DnsRecord::CNAME(name), RData::CNAME(inner)) => name == inner,
+			(DnsRecord::TXT(text), RData::TXT(inner)) => {
+				// long TXT values are split into 255 byte strings on the wire
+				&inner
+					.iter()
+					.map(|s| String::from_utf8_lossy(s))
+					.collect::<String>()
+					== text
+			}
+			(DnsRecord::NS(name), RData::NS(inner)) => name == inner,
+			(DnsRecord::SOA { serial }, RData::SOA(inner)) => {
+				*serial == inner.serial()
+			}
+			(DnsRecord::PTR(name), RData::PTR(inner)) => name == inner,
+			(
+				DnsRecord::SRV {
+					priority,
+					weight,
+					port,
+					target,
+				},
+				RData::SRV(inner),
+			) => {
+				target == inner.target()
+					&& *port == inner.port()
+					&& priority.map_or(true, |p| p == inner.priority())
+					&& weight.map_or(true, |w| w == inner.weight())
+			}
+			_ => false,
+		}
+	}
+}
+
+impl From<DnsRecordType> for RecordType {
+	fn from(ty: DnsRecordType) -> Self {
+		match ty {
+			DnsRecordType::A => RecordType::A,
+			DnsRecordType::AAAA => RecordType::AAAA,
+			DnsRecordType::MX => RecordType::MX,
+			DnsRecordType::CNAME => RecordType::CNAME,
+			DnsRecordType::TXT => RecordType::TXT,
+			DnsRecordType::NS => RecordType::NS,
+			DnsRecordType::SOA => RecordType::SOA,
+			DnsRecordType::PTR => RecordType::PTR,
+			DnsRecordType::SRV => RecordType::SRV,
+		}
+	}
+}
 
 #[derive(Debug)]
 pub struct DnsCheck {
-	name: Ipv4Addr,
-	record: DnsRecord,
+	pub server: SocketAddrV4,
+	pub protocol: Protocol,
+	pub name: String,
+	pub record_type: DnsRecordType,
+	pub matching: DnsMatch,
+	pub expected: Vec<DnsRecord>,
 }
 
 #[async_trait]
 impl Service for DnsCheck {
 	async fn is_up(&self) -> Result<()> {
-		let resolver = AsyncResolver::tokio_from_system_conf()?;
+		// ask the team's server directly rather than whatever the engine host
+		// resolves through
+		let mut config = ResolverConfig::new();
+		config.add_name_server(NameServerConfig {
+			socket_addr: SocketAddr::V4(self.server),
+			protocol: self.protocol,
+			tls_dns_name: None,
+			trust_nx_responses: true,
+			tls_config: None,
+		});
+
+		let resolver = TokioAsyncResolver::tokio(
+			config,
+			ResolverOpts {
+				cache_size: 0,
+				use_hosts_file: false,
+				..ResolverOpts::default()
+			},
+		)?;
 		let lookup = resolver
 			.lookup(
-				self.name,
-				match self.record {
-					DnsRecord::A { .. } => RecordType::A,
-					DnsRecord::AAAA { .. } => RecordType::AAAA,
-				},
+				self.name.as_str(),
+				self.record_type.into(),
 				DnsRequestOptions::default(),
 			)
 			.await?;
 
-		if lookup
-			.record_iter()
-			.find(|r| match r.into_data() {
-				RData::A(inner) => {
-					if let DnsRecord::A { addr } = self.record {
-						addr == inner
-					} else {
-						false
-					}
+		let found =
+			|record: &DnsRecord| lookup.iter().any(|r| record.matches(r));
+		match self.matching {
+			DnsMatch::Exists => {
+				if lookup.iter().next().is_none() {
+					bail!("No {:?} records returned", self.record_type)
+				}
+			}
+			DnsMatch::OneOf => {
+				if !self.expected.iter().any(found) {
+					bail!("Failed to verify that record exists")
 				}
-				RData::AAAA(inner) => {
-					if let DnsRecord::AAAA { addr } = self.record {
-						addr == inner
-					} else {
-						false
-					}
+			}
+			DnsMatch::AllOf => {
+				if let Some(missing) =
+					self.expected.iter().find(|&record| !found(record))
+				{
+					bail!("Failed to verify that record exists: {:?}", missing)
 				}
-				_ => false,
-			})
-			.is_some()
-		{
-			Ok(())
-		} else {
-			bail!("Failed to verify that record exists")
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use trust_dns_proto::rr::rdata::{MX, SRV, TXT};
+
+	fn name(s: &str) -> Name { Name::from_str(s).unwrap() }
+
+	#[test]
+	fn mx_preference_is_optional() {
+		let data = RData::MX(MX::new(10, name("mail.team.local.")));
+		let any = DnsRecord::parse(DnsRecordType::MX, "mail.team.local.");
+		let ten = DnsRecord::parse(DnsRecordType::MX, "10 mail.team.local.");
+		let five = DnsRecord::parse(DnsRecordType::MX, "5 mail.team.local.");
+
+		assert!(any.unwrap().matches(&data));
+		assert!(ten.unwrap().matches(&data));
+		assert!(!five.unwrap().matches(&data));
+		assert!(DnsRecord::parse(DnsRecordType::MX, "1 2 mail.").is_err());
+	}
+
+	#[test]
+	fn srv_takes_two_or_four_fields() {
+		let data = RData::SRV(SRV::new(0, 5, 5060, name("sip.team.local.")));
+		for value in &["5060 sip.team.local.", "0 5 5060 sip.team.local."] {
+			let record = DnsRecord::parse(DnsRecordType::SRV, value).unwrap();
+			assert!(record.matches(&data), "{} didn't match", value);
+		}
+
+		let wrong_weight =
+			DnsRecord::parse(DnsRecordType::SRV, "0 6 5060 sip.team.local.");
+		assert!(!wrong_weight.unwrap().matches(&data));
+		for value in &["sip.team.local.", "5 5060 sip.team.local."] {
+			assert!(DnsRecord::parse(DnsRecordType::SRV, value).is_err());
 		}
 	}
+
+	#[test]
+	fn txt_strings_are_joined() {
+		let data = RData::TXT(TXT::new(vec!["v=spf1 ".into(), "-all".into()]));
+		let joined = DnsRecord::parse(DnsRecordType::TXT, "v=spf1 -all");
+		let part = DnsRecord::parse(DnsRecordType::TXT, "v=spf1 ");
+
+		assert!(joined.unwrap().matches(&data));
+		assert!(!part.unwrap().matches(&data));
+	}
+
+	#[test]
+	fn soa_compares_serials() {
+		let record = DnsRecord::parse(DnsRecordType::SOA, " 2021030801 ");
+		assert!(matches!(record, Ok(DnsRecord::SOA { serial: 2021030801 })));
+		assert!(DnsRecord::parse(DnsRecordType::SOA, "ns1.").is_err());
+	}
 }
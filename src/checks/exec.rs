@@ -0,0 +1,88 @@
+use super::Service;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::{
+	net::Ipv4Addr,
+	os::unix::process::CommandExt,
+	path::PathBuf,
+	process::{self, Stdio},
+};
+use tokio::process::Command;
+
+// Kills the program's whole process group once the check is done with it,
+// timed out or not. Killing just the program would leave whatever a wrapper
+// script started (curl, nc, ...) running, one more every round.
+struct ProcessGroup(i32);
+
+impl Drop for ProcessGroup {
+	fn drop(&mut self) {
+		// SAFETY: only sends a signal, a group that's already gone is fine
+		unsafe {
+			libc::kill(-self.0, libc::SIGKILL);
+		}
+	}
+}
+
+// Runs an existing check script. The target is handed over both as
+// `SCYLLA_*` environment variables and as `{{team}}`, `{{box}}`, `{{ip}}` and
+// `{{port}}` placeholders in the arguments.
+#[derive(Debug)]
+pub struct ExecCheck {
+	pub program: PathBuf,
+	pub args: Vec<String>,
+	pub team_id: String,
+	pub vm_id: String,
+	pub ip: Ipv4Addr,
+	pub port: Option<u16>,
+}
+
+impl ExecCheck {
+	fn vars(&self) -> Vec<(&'static str, String)> {
+		vec![
+			("team", self.team_id.clone()),
+			("box", self.vm_id.clone()),
+			("ip", self.ip.to_string()),
+			("port", self.port.map(|p| p.to_string()).unwrap_or_default()),
+		]
+	}
+}
+
+#[async_trait]
+impl Service for ExecCheck {
+	async fn is_up(&self) -> Result<()> {
+		let vars = self.vars();
+		let mut cmd = process::Command::new(&self.program);
+		for arg in &self.args {
+			cmd.arg(vars.iter().fold(arg.clone(), |acc, (name, value)| {
+				acc.replace(&format!("{{{{{}}}}}", name), value)
+			}));
+		}
+		for (name, value) in &vars {
+			cmd.env(format!("SCYLLA_{}", name.to_uppercase()), value);
+		}
+
+		// its own group, with the program's pid as the group id
+		cmd.process_group(0)
+			.stdin(Stdio::null())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped());
+
+		let child = Command::from(cmd).kill_on_drop(true).spawn()?;
+		let _group = child.id().map(|pid| ProcessGroup(pid as i32));
+		let output = child.wait_with_output().await?;
+
+		if !output.status.success() {
+			let stderr = String::from_utf8_lossy(&output.stderr);
+			match stderr.trim() {
+				"" => bail!(
+					"{} exited with {}",
+					self.program.display(),
+					output.status
+				),
+				reason => bail!("{}", reason),
+			}
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,48 @@
+use super::{tls, Service};
+use anyhow::{bail, Result};
+use async_ftp::FtpStream;
+use async_trait::async_trait;
+use std::net::SocketAddrV4;
+
+#[derive(Debug)]
+pub struct FtpCheck {
+	pub remote: SocketAddrV4,
+	pub username: Option<String>,
+	pub password: Option<String>,
+	pub path: String,
+	pub starttls: bool,
+	pub content_hash: String,
+}
+
+#[async_trait]
+impl Service for FtpCheck {
+	async fn is_up(&self) -> Result<()> {
+		let mut stream = FtpStream::connect(&self.remote).await?;
+		if self.starttls {
+			stream = stream
+				.into_secure(
+					tls::insecure_client_config(),
+					tls::placeholder_domain(),
+				)
+				.await?;
+		}
+
+		// fall back to anonymous login if no credentials were given
+		stream
+			.login(
+				self.username.as_deref().unwrap_or("anonymous"),
+				self.password.as_deref().unwrap_or("anonymous"),
+			)
+			.await?;
+
+		let file = stream.simple_retr(&self.path).await?;
+		let _ = stream.quit().await;
+
+		let digest = md5::compute(file.get_ref());
+		if format!("{:x}", digest) != self.content_hash {
+			bail!("Hash comparison failed")
+		}
+
+		Ok(())
+	}
+}
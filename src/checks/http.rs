@@ -1,33 +1,178 @@
-use super::Service;
-use anyhow::{bail, Result};
+use super::{tls, Service};
+use crate::config::{HttpOptions, HttpStep, TlsPolicy};
+use anyhow::{anyhow, bail, Context as _, Result};
 use async_trait::async_trait;
-use reqwest::{Client, Method, Request, Url};
-use std::{net::SocketAddrV4, str::FromStr};
+use reqwest::{header::HOST, Client, RequestBuilder, Response, Url};
+use std::{collections::HashMap, net::SocketAddrV4, str::FromStr};
+
+// substitutes `{{name}}` with values extracted by earlier scenario steps
+fn render(template: &str, vars: &HashMap<String, String>) -> String {
+	vars.iter().fold(template.to_owned(), |acc, (name, value)| {
+		acc.replace(&format!("{{{{{}}}}}", name), value)
+	})
+}
+
+// the certificate is judged by `tls::verify_policy`, not by reqwest
+fn build_client(tls: &Option<TlsPolicy>, cookies: bool) -> Result<Client> {
+	Ok(Client::builder()
+		.danger_accept_invalid_certs(tls.is_some())
+		.cookie_store(cookies)
+		.build()?)
+}
+
+async fn check_tls(
+	remote: &SocketAddrV4,
+	tls: &Option<TlsPolicy>,
+) -> Result<()> {
+	match tls {
+		Some(policy) => tls::verify_policy(*remote, policy).await,
+		None => Ok(()),
+	}
+}
+
+fn build_request(
+	client: &Client,
+	remote: &SocketAddrV4,
+	ssl: bool,
+	opts: &HttpOptions,
+	vars: &HashMap<String, String>,
+) -> Result<RequestBuilder> {
+	let url = Url::from_str(&format!(
+		"http{ssl}://{}{}",
+		remote.to_string(),
+		render(&opts.path, vars),
+		ssl = if ssl { "s" } else { "" }
+	))?;
+
+	let mut req = client.request(opts.method.0.to_owned(), url);
+	if let Some(ref host) = opts.host {
+		req = req.header(HOST, host);
+	}
+	for (name, value) in opts.headers.iter() {
+		req = req.header(name, render(value, vars));
+	}
+	if let Some(ref body) = opts.body {
+		req = req.body(render(body, vars));
+	}
+
+	Ok(req)
+}
+
+// runs every assertion in `opts` against the response, handing back the body
+async fn verify_response(res: Response, opts: &HttpOptions) -> Result<String> {
+	let status = res.status();
+	if opts.status.is_empty() {
+		if !status.is_success() {
+			bail!("Unexpected status code: {}", status)
+		}
+	} else if !opts.status.contains(&status.as_u16()) {
+		bail!("Unexpected status code: {}", status)
+	}
+
+	for (name, expect) in opts.response_headers.iter() {
+		let value = res
+			.headers()
+			.get(name)
+			.ok_or(anyhow!("Missing response header: {}", name))?
+			.to_str()?;
+		if !expect.is_match(value) {
+			bail!("Response header {} was not {}", name, expect)
+		}
+	}
+
+	let text = res.text().await?;
+	if let Some(ref hash) = opts.content_hash {
+		let digest = md5::compute(text.as_bytes());
+		if !(&format!("{:x}", digest) == hash) {
+			bail!("Hash comparison failed")
+		}
+	}
+
+	for expect in opts.expect.iter() {
+		if !expect.is_match(&text) {
+			bail!("Response body was not {}", expect)
+		}
+	}
+
+	Ok(text)
+}
 
 #[derive(Debug)]
 pub struct HttpCheck {
 	pub remote: SocketAddrV4,
-	pub method: Method,
-	pub ssl: bool,
-	pub content_hash: Option<String>,
+	// `None` for plain HTTP
+	pub tls: Option<TlsPolicy>,
+	pub options: HttpOptions,
 }
 
 #[async_trait]
 impl Service for HttpCheck {
 	async fn is_up(&self) -> Result<()> {
-		let url = Url::from_str(&format!(
-			"http{ssl}://{}",
-			self.remote.to_string(),
-			ssl = if self.ssl { "s" } else { "" }
-		))?;
-
-		let req = Request::new(self.method.to_owned(), url);
-		let res = Client::new().execute(req).await?;
-
-		if let Some(ref hash) = self.content_hash {
-			let digest = md5::compute(res.text().await?.as_bytes());
-			if !(&format!("{:x}", digest) == hash) {
-				bail!("Hash comparison failed")
+		check_tls(&self.remote, &self.tls).await?;
+
+		let req = build_request(
+			&build_client(&self.tls, false)?,
+			&self.remote,
+			self.tls.is_some(),
+			&self.options,
+			&HashMap::new(),
+		)?;
+
+		verify_response(req.send().await?, &self.options)
+			.await
+			.map(|_| ())
+	}
+}
+
+#[derive(Debug)]
+pub struct HttpScenarioCheck {
+	pub remote: SocketAddrV4,
+	pub tls: Option<TlsPolicy>,
+	pub steps: Vec<HttpStep>,
+}
+
+#[async_trait]
+impl Service for HttpScenarioCheck {
+	async fn is_up(&self) -> Result<()> {
+		check_tls(&self.remote, &self.tls).await?;
+
+		// one jar for the whole scenario so a login sticks between steps
+		let client = build_client(&self.tls, true)?;
+		let mut vars = HashMap::new();
+
+		for (i, step) in self.steps.iter().enumerate() {
+			let failed = || format!("Step {} failed", i + 1);
+			let mut req = build_request(
+				&client,
+				&self.remote,
+				self.tls.is_some(),
+				&step.request,
+				&vars,
+			)?;
+			if !step.form.is_empty() {
+				req = req.form(
+					&step
+						.form
+						.iter()
+						.map(|(name, value)| (name, render(value, &vars)))
+						.collect::<HashMap<_, _>>(),
+				);
+			}
+
+			let res = req.send().await.with_context(failed)?;
+			let body = verify_response(res, &step.request)
+				.await
+				.with_context(failed)?;
+
+			for (name, pattern) in step.extract.iter() {
+				// prefer the first capture group, if there is one
+				let value = pattern
+					.0
+					.captures(&body)
+					.and_then(|c| c.get(1).or(c.get(0)))
+					.ok_or(anyhow!("Could not extract {}", name))
+					.with_context(failed)?;
+				vars.insert(name.clone(), value.as_str().to_owned());
 			}
 		}
 
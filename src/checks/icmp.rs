@@ -0,0 +1,120 @@
+use super::Service;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use rand::Rng;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::{
+	collections::HashSet,
+	net::{self, SocketAddrV4},
+	time::Duration,
+};
+use tokio::{
+	net::UdpSocket,
+	time::{self, Instant},
+};
+
+// gap between echo requests, so a burst doesn't get rate limited into loss
+const SEND_INTERVAL: Duration = Duration::from_millis(100);
+
+// Datagram ICMP sockets don't need CAP_NET_RAW on Linux as long as
+// net.ipv4.ping_group_range covers us. Raw sockets are the fallback for
+// everywhere else.
+fn open_socket(remote: SocketAddrV4) -> Result<UdpSocket> {
+	let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4))
+		.or_else(|_| {
+			Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))
+		})?;
+	socket.set_nonblocking(true)?;
+	socket.connect(&SockAddr::from(SocketAddrV4::new(*remote.ip(), 0)))?;
+	Ok(UdpSocket::from_std(net::UdpSocket::from(socket))?)
+}
+
+fn checksum(data: &[u8]) -> u16 {
+	let mut sum = data
+		.chunks(2)
+		.map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+		.sum::<u32>();
+	while sum >> 16 != 0 {
+		sum = (sum & 0xffff) + (sum >> 16);
+	}
+	!(sum as u16)
+}
+
+fn echo_request(id: u16, seq: u16, token: &[u8]) -> Vec<u8> {
+	let mut packet = vec![8, 0, 0, 0];
+	packet.extend_from_slice(&id.to_be_bytes());
+	packet.extend_from_slice(&seq.to_be_bytes());
+	packet.extend_from_slice(token);
+	let sum = checksum(&packet);
+	packet[2..4].copy_from_slice(&sum.to_be_bytes());
+	packet
+}
+
+// Returns the sequence number if `packet` is a reply to one of our requests.
+// The kernel rewrites the identifier on datagram sockets, so replies are
+// recognized by the random token in their payload instead.
+fn echo_reply_seq(mut packet: &[u8], token: &[u8]) -> Option<u16> {
+	// raw sockets (and some platforms' datagram sockets) hand over the IP
+	// header too
+	if packet.first()? >> 4 == 4 {
+		packet = packet.get((packet[0] & 0xf) as usize * 4..)?;
+	}
+	if *packet.first()? != 0 || packet.get(8..)? != token {
+		return None;
+	}
+	Some(u16::from_be_bytes([packet[6], packet[7]]))
+}
+
+#[derive(Debug)]
+pub struct IcmpCheck {
+	pub remote: SocketAddrV4,
+	pub count: u16,
+	// highest tolerated packet loss, in percent
+	pub max_loss: u8,
+	// how long replies are waited for, sends included
+	pub timeout: Duration,
+}
+
+#[async_trait]
+impl Service for IcmpCheck {
+	async fn is_up(&self) -> Result<()> {
+		let socket = open_socket(self.remote)?;
+		let id = rand::thread_rng().gen::<u16>();
+		let token = rand::thread_rng().gen::<[u8; 8]>();
+		let deadline = Instant::now() + self.timeout;
+
+		for seq in 0..self.count {
+			if seq > 0 {
+				time::sleep(SEND_INTERVAL).await;
+			}
+			socket.send(&echo_request(id, seq, &token)).await?;
+		}
+
+		let mut replies = HashSet::new();
+		let mut buf = [0; 1500];
+		while replies.len() < self.count as usize {
+			let len =
+				match time::timeout_at(deadline, socket.recv(&mut buf)).await {
+					Ok(len) => len?,
+					Err(_) => break,
+				};
+			if let Some(seq) = echo_reply_seq(&buf[..len], &token) {
+				if seq < self.count {
+					replies.insert(seq);
+				}
+			}
+		}
+
+		let lost = self.count as usize - replies.len();
+		let loss = lost * 100 / self.count as usize;
+		if loss > self.max_loss as usize {
+			bail!(
+				"{}% packet loss ({} of {} echo requests unanswered)",
+				loss,
+				lost,
+				self.count
+			)
+		}
+		Ok(())
+	}
+}
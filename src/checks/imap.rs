@@ -0,0 +1,151 @@
+use super::{conn::LineConn, Service};
+use crate::config::OutputMatch;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::net::SocketAddrV4;
+
+fn quote(s: &str) -> String {
+	format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[derive(Default)]
+struct Response {
+	lines: Vec<String>,
+	literals: Vec<String>,
+}
+
+struct Session {
+	conn: LineConn,
+	tag: u32,
+}
+
+impl Session {
+	async fn command(&mut self, cmd: &str) -> Result<Response> {
+		self.tag += 1;
+		let tag = format!("a{}", self.tag);
+		self.conn.write_line(&format!("{} {}", tag, cmd)).await?;
+
+		let mut response = Response::default();
+		loop {
+			let line = self.conn.read_line().await?;
+			if let Some(status) = line
+				.strip_prefix(tag.as_str())
+				.and_then(|s| s.strip_prefix(' '))
+			{
+				if status.starts_with("OK") {
+					return Ok(response);
+				}
+				bail!("Unexpected reply from IMAP server: {}", line)
+			}
+
+			// a trailing `{n}` announces n bytes of literal data
+			if let Some(len) = line
+				.strip_suffix('}')
+				.and_then(|l| l.rsplit('{').next())
+				.and_then(|n| n.parse::<usize>().ok())
+			{
+				let literal = self.conn.read_exact(len).await?;
+				response
+					.literals
+					.push(String::from_utf8_lossy(&literal).into_owned());
+			}
+			response.lines.push(line);
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct ImapCheck {
+	pub remote: SocketAddrV4,
+	// implicit TLS, usually on 993
+	pub tls: bool,
+	pub starttls: bool,
+	pub username: String,
+	pub password: String,
+	pub mailbox: String,
+	pub expect: Option<OutputMatch>,
+}
+
+impl ImapCheck {
+	// Logs in and selects the mailbox. If `expect` is given, messages are
+	// fetched newest first until one of them matches, which `delete` then
+	// expunges.
+	pub async fn search(
+		&self,
+		expect: Option<&OutputMatch>,
+		delete: bool,
+	) -> Result<()> {
+		let mut session = Session {
+			conn: LineConn::connect(self.remote, self.tls).await?,
+			tag: 0,
+		};
+
+		let greeting = session.conn.read_line().await?;
+		if !greeting.starts_with("* OK") {
+			bail!("Unexpected greeting from IMAP server: {}", greeting)
+		}
+
+		if self.starttls {
+			session.command("STARTTLS").await?;
+			session.conn = session.conn.upgrade().await?;
+		}
+
+		session
+			.command(&format!(
+				"LOGIN {} {}",
+				quote(&self.username),
+				quote(&self.password)
+			))
+			.await?;
+
+		let selected = session
+			.command(&format!("SELECT {}", quote(&self.mailbox)))
+			.await?;
+		let exists = selected
+			.lines
+			.iter()
+			.find_map(|l| {
+				l.strip_prefix("* ")?
+					.strip_suffix(" EXISTS")?
+					.parse::<u32>()
+					.ok()
+			})
+			.unwrap_or(0);
+
+		if let Some(expect) = expect {
+			let mut found = false;
+			for id in (1..=exists).rev() {
+				let fetched = session
+					.command(&format!("FETCH {} BODY.PEEK[]", id))
+					.await?;
+				if fetched.literals.iter().any(|body| expect.is_match(body)) {
+					if delete {
+						session
+							.command(&format!(
+								"STORE {} +FLAGS (\\Deleted)",
+								id
+							))
+							.await?;
+						session.command("EXPUNGE").await?;
+					}
+					found = true;
+					break;
+				}
+			}
+
+			if !found {
+				bail!("No message in {} was {}", self.mailbox, expect)
+			}
+		}
+
+		let _ = session.command("LOGOUT").await;
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl Service for ImapCheck {
+	async fn is_up(&self) -> Result<()> {
+		self.search(self.expect.as_ref(), false).await
+	}
+}
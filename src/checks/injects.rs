@@ -1,3 +1,4 @@
+use super::link_dependencies;
 use crate::config::{Cfg, SharedService};
 use anyhow::{Error, Result};
 use chrono::{Duration, Utc};
@@ -28,9 +29,11 @@ pub async fn wait(cfg: Arc<Cfg>) -> Result<()> {
 				for svc in bx_svcs {
 					for team in new_cfg.teams.iter() {
 						services.push(SharedService::from_config(
+							&new_cfg.registry,
 							svc,
 							team,
 							(&bx_id, &new_cfg.boxes[bx_id]),
+							new_cfg.checks.get_timeout(),
 						)?);
 
 						println!(
@@ -40,6 +43,7 @@ pub async fn wait(cfg: Arc<Cfg>) -> Result<()> {
 					}
 				}
 			}
+			link_dependencies(&mut services)?;
 
 			Ok::<_, Error>(())
 		}
@@ -0,0 +1,80 @@
+use super::Service;
+use crate::config::OutputMatch;
+use anyhow::{bail, Context as _, Result};
+use async_trait::async_trait;
+use ldap3::{LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use std::net::SocketAddrV4;
+
+#[derive(Debug)]
+pub struct LdapCheck {
+	pub remote: SocketAddrV4,
+	// LDAPS, usually on 636
+	pub tls: bool,
+	pub starttls: bool,
+	pub bind_dn: String,
+	pub password: String,
+	pub base_dn: String,
+	pub filter: String,
+	pub min_entries: usize,
+	// some entry must have this attribute with a matching value
+	pub attribute: Option<(String, OutputMatch)>,
+}
+
+#[async_trait]
+impl Service for LdapCheck {
+	async fn is_up(&self) -> Result<()> {
+		let url = format!(
+			"{}://{}",
+			if self.tls { "ldaps" } else { "ldap" },
+			self.remote
+		);
+		let settings = LdapConnSettings::new()
+			.set_starttls(self.starttls)
+			.set_no_tls_verify(true);
+		let (conn, mut ldap) =
+			LdapConnAsync::with_settings(settings, &url).await?;
+		ldap3::drive!(conn);
+
+		ldap.simple_bind(&self.bind_dn, &self.password)
+			.await?
+			.success()
+			.context("Bind failed")?;
+
+		let attrs = match self.attribute {
+			Some((ref name, _)) => vec![name.as_str()],
+			None => vec!["1.1"],
+		};
+		let (entries, _) = ldap
+			.search(&self.base_dn, Scope::Subtree, &self.filter, attrs)
+			.await?
+			.success()
+			.context("Search failed")?;
+		let _ = ldap.unbind().await;
+
+		if entries.len() < self.min_entries {
+			bail!(
+				"Search returned {} entries, expected at least {}",
+				entries.len(),
+				self.min_entries
+			)
+		}
+
+		if let Some((ref name, ref expect)) = self.attribute {
+			let found = entries.into_iter().any(|entry| {
+				// attribute names are case-insensitive
+				SearchEntry::construct(entry)
+					.attrs
+					.iter()
+					.any(|(k, values)| {
+						k.eq_ignore_ascii_case(name)
+							&& values.iter().any(|v| expect.is_match(v))
+					})
+			});
+			if !found {
+				bail!("No entry had {} {}", name, expect)
+			}
+		}
+
+		Ok(())
+	}
+}
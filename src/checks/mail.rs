@@ -0,0 +1,60 @@
+use super::{
+	imap::ImapCheck,
+	pop3::Pop3Check,
+	smtp::{self, SmtpCheck},
+	Service,
+};
+use crate::config::OutputMatch;
+use anyhow::{bail, Context as _, Result};
+use async_trait::async_trait;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+#[derive(Debug)]
+pub enum Mailbox {
+	Pop3(Pop3Check),
+	Imap(ImapCheck),
+}
+
+impl Mailbox {
+	// deletes the message once found, or every round would leave another one
+	// behind in the team's mailbox
+	async fn take(&self, expect: &OutputMatch) -> Result<()> {
+		match self {
+			Mailbox::Pop3(check) => check.search(Some(expect), true).await,
+			Mailbox::Imap(check) => check.search(Some(expect), true).await,
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct MailCheck {
+	pub send: SmtpCheck,
+	pub receive: Mailbox,
+	pub poll_interval: Duration,
+	// how long delivery may take before the message counts as lost
+	pub timeout: Duration,
+}
+
+#[async_trait]
+impl Service for MailCheck {
+	async fn is_up(&self) -> Result<()> {
+		let nonce = smtp::nonce();
+		self.send
+			.send(&nonce)
+			.await
+			.context("Failed to send message")?;
+
+		let expect = OutputMatch::Contains(nonce);
+		let deadline = Instant::now() + self.timeout;
+		loop {
+			match self.receive.take(&expect).await {
+				Ok(()) => return Ok(()),
+				Err(e) if Instant::now() + self.poll_interval >= deadline => {
+					bail!("Sent message never arrived: {:#}", e)
+				}
+				Err(_) => time::sleep(self.poll_interval).await,
+			}
+		}
+	}
+}
@@ -1,20 +1,47 @@
+pub mod composite;
+pub mod conn;
 pub mod dns;
+pub mod exec;
+pub mod ftp;
 pub mod http;
+pub mod icmp;
+pub mod imap;
 pub mod injects;
+pub mod ldap;
+pub mod mail;
+pub mod plugin;
+pub mod pool;
+pub mod pop3;
+pub mod registry;
+pub mod script;
+pub mod smb;
+pub mod smtp;
+pub mod sql;
+pub mod ssh;
 pub mod tcp;
+pub mod tls;
 pub mod udp;
 
 use crate::{
-	config::Cfg,
+	config::{Cfg, SharedService},
 	db::{
 		mutation::{persist_downtime, persist_uptime},
 		PgPool,
 	},
 };
-use anyhow::{anyhow, Context as _, Result};
+use anyhow::{anyhow, bail, Context as _, Result};
 use async_trait::async_trait;
-use futures::future;
-use std::{fmt::Debug, sync::Arc, time::Duration};
+use chrono::{DateTime, Utc};
+use futures::{
+	future::{self, BoxFuture, Shared},
+	FutureExt,
+};
+use std::{
+	fmt::Debug,
+	future::Future,
+	sync::Arc,
+	time::{Duration, Instant},
+};
 use tokio::{
 	sync::mpsc::{UnboundedReceiver, UnboundedSender},
 	task, time,
@@ -28,9 +55,90 @@ pub struct SvcMeta {
 }
 
 #[derive(Debug, Clone)]
-pub enum ChanMsg {
-	Error(Arc<SvcMeta>),
-	Uptime(Arc<SvcMeta>),
+pub enum Outcome {
+	Up,
+	Down(String),
+	Timeout,
+}
+
+impl Outcome {
+	pub fn status(&self) -> &'static str {
+		match self {
+			Outcome::Up => "up",
+			Outcome::Down(_) => "down",
+			Outcome::Timeout => "timeout",
+		}
+	}
+
+	pub fn reason(&self) -> Option<String> {
+		match self {
+			Outcome::Up => None,
+			Outcome::Down(reason) => Some(reason.clone()),
+			Outcome::Timeout => Some("Check timed out".into()),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ChanMsg {
+	pub meta: Arc<SvcMeta>,
+	pub round: i32,
+	pub checked_at: DateTime<Utc>,
+	pub latency: Duration,
+	pub outcome: Outcome,
+	// the account a credential pool picked for this poll
+	pub username: Option<String>,
+}
+
+fn report(
+	chan: &UnboundedSender<ChanMsg>,
+	cx: Arc<SvcMeta>,
+	round: i32,
+	checked_at: DateTime<Utc>,
+	latency: Duration,
+	outcome: Outcome,
+	username: Option<String>,
+) -> Result<Outcome> {
+	let message = ChanMsg {
+		meta: cx.clone(),
+		round,
+		checked_at,
+		latency,
+		outcome: outcome.clone(),
+		username,
+	};
+	chan.send(message).with_context(|| {
+		format!("Failed to send poll message to channel: {:?}", cx.clone())
+	})?;
+	Ok(outcome)
+}
+
+// Times a single `is_up` and reports how it went.
+pub async fn probe(
+	check: impl Future<Output = Result<()>>,
+	chan: &UnboundedSender<ChanMsg>,
+	timeout: Duration,
+	cx: Arc<SvcMeta>,
+	round: i32,
+	username: Option<String>,
+) -> Result<Outcome> {
+	let checked_at = Utc::now();
+	let started = Instant::now();
+	let outcome = match time::timeout(timeout, check).await {
+		Ok(Ok(())) => Outcome::Up,
+		// alternate formatting keeps the whole context chain
+		Ok(Err(e)) => Outcome::Down(format!("{:#}", e)),
+		Err(_) => Outcome::Timeout,
+	};
+	report(
+		chan,
+		cx,
+		round,
+		checked_at,
+		started.elapsed(),
+		outcome,
+		username,
+	)
 }
 
 #[async_trait]
@@ -41,39 +149,146 @@ pub trait Service: Send + Sync + Debug {
 		chan: UnboundedSender<ChanMsg>,
 		timeout: Duration,
 		cx: Arc<SvcMeta>,
-	) -> Result<()> {
-		let message = match time::timeout(timeout, self.is_up()).await {
-			Ok(_) => ChanMsg::Uptime(cx.clone()),
-			Err(_) => ChanMsg::Error(cx.clone()),
+		round: i32,
+	) -> Result<Outcome> {
+		probe(self.is_up(), &chan, timeout, cx, round, None).await
+	}
+}
+
+// Points every service's `dependency` at the service it names in `dependsOn`,
+// which must be on the same team's box.
+pub fn link_dependencies(services: &mut [SharedService]) -> Result<()> {
+	for i in 0..services.len() {
+		let svc = &services[i];
+		let dependency = match svc.depends_on {
+			Some(ref id) => Some(
+				services
+					.iter()
+					.position(|dep| {
+						dep.meta.svc_id == *id
+							&& dep.meta.vm_id == svc.meta.vm_id
+							&& dep.meta.team_id == svc.meta.team_id
+					})
+					.ok_or_else(|| {
+						anyhow!(
+							"Service {} depends on unknown service {}",
+							svc.meta.svc_id,
+							id
+						)
+					})?,
+			),
+			None => None,
 		};
-		chan.send(message).with_context(|| {
-			format!("Failed to send poll message to channel: {:?}", cx.clone())
-		})
+		services[i].dependency = dependency;
+	}
+
+	// any chain longer than the number of services has to loop
+	for i in 0..services.len() {
+		let mut next = services[i].dependency;
+		for _ in 0..services.len() {
+			next = match next {
+				Some(j) => services[j].dependency,
+				None => break,
+			};
+		}
+		if next.is_some() {
+			bail!(
+				"Service {} has circular dependencies",
+				services[i].meta.svc_id
+			)
+		}
 	}
+
+	Ok(())
+}
+
+type Polled<'a> = Shared<BoxFuture<'a, Option<Outcome>>>;
+
+// Dependents wait on their dependency's poll and are only probed if it came
+// back up. `polls` memoizes each service's poll so it runs once per round.
+// Every probe gets the full timeout, so a round takes as long as its longest
+// dependency chain times the timeout.
+fn schedule<'a>(
+	services: &'a [SharedService],
+	i: usize,
+	polls: &mut Vec<Option<Polled<'a>>>,
+	tx: &UnboundedSender<ChanMsg>,
+	timeout: Duration,
+	round: i32,
+) -> Polled<'a> {
+	if let Some(ref poll) = polls[i] {
+		return poll.clone();
+	}
+
+	let svc = &services[i];
+	let dependency = svc.dependency.map(|d| {
+		(
+			&services[d].meta.svc_id,
+			schedule(services, d, polls, tx, timeout, round),
+		)
+	});
+	let tx = tx.clone();
+	let poll = async move {
+		if let Some((id, dependency)) = dependency {
+			if !matches!(dependency.await, Some(Outcome::Up)) {
+				let outcome = Outcome::Down(format!("dependency {} down", id));
+				return report(
+					&tx,
+					svc.meta.clone(),
+					round,
+					Utc::now(),
+					Duration::default(),
+					outcome,
+					None,
+				)
+				.ok();
+			}
+		}
+		svc.inner
+			.poll(tx, timeout, svc.meta.clone(), round)
+			.await
+			.ok()
+	}
+	.boxed()
+	.shared();
+
+	polls[i] = Some(poll.clone());
+	poll
 }
 
-pub async fn enter_event_loop(cfg: Arc<Cfg>, tx: UnboundedSender<ChanMsg>) {
+// `last_round` is the last round already in the database, so a restarted
+// engine doesn't reuse round numbers
+pub async fn enter_event_loop(
+	cfg: Arc<Cfg>,
+	tx: UnboundedSender<ChanMsg>,
+	last_round: i32,
+) {
 	let jittered = cfg.checks.get_interval();
 	let mut interval = time::interval(jittered);
 
 	println!("Scoring with interval: {:?}", jittered);
 
+	let mut round = last_round;
 	loop {
+		round += 1;
 		task::spawn({
 			let cfg = cfg.clone();
 			let shared_tx = tx.clone();
 
 			async move {
 				let services = cfg._services.lock().await;
+				let timeout = cfg.checks.get_timeout();
 
-				future::join_all(services.iter().map(|svc| {
-					svc.inner.poll(
-						shared_tx.clone(),
-						Duration::from_secs(cfg.checks.timeout as u64),
-						svc.meta.clone(),
-					)
-				}))
-				.await;
+				let mut polls = vec![None; services.len()];
+				let all = (0..services.len())
+					.map(|i| {
+						schedule(
+							&services, i, &mut polls, &shared_tx, timeout,
+							round,
+						)
+					})
+					.collect::<Vec<_>>();
+				future::join_all(all).await;
 			}
 		});
 
@@ -90,9 +305,175 @@ pub async fn enter_recv_loop(
 			.recv()
 			.await
 			.ok_or(anyhow!("Failed to recieve message from channel!"))?;
-		match m {
-			ChanMsg::Uptime(meta) => persist_uptime(&meta, pool.clone()).await,
-			ChanMsg::Error(meta) => persist_downtime(&meta, pool.clone()).await,
-		}?;
+		let persisted = match m.outcome {
+			Outcome::Up => persist_uptime(&m, pool.clone()).await,
+			_ => persist_downtime(&m, pool.clone()).await,
+		};
+		// a lost result shouldn't stop every later one from being scored
+		if let Err(e) = persisted {
+			eprintln!("{:#}", e);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use tokio::{runtime::Runtime, sync::mpsc};
+
+	#[derive(Debug, Default)]
+	struct Stub {
+		down: bool,
+		probes: Arc<AtomicUsize>,
+	}
+
+	#[async_trait]
+	impl Service for Stub {
+		async fn is_up(&self) -> Result<()> {
+			self.probes.fetch_add(1, Ordering::SeqCst);
+			if self.down {
+				bail!("down")
+			}
+			Ok(())
+		}
+	}
+
+	fn service(
+		team: &str,
+		id: &str,
+		depends_on: Option<&str>,
+		stub: Stub,
+	) -> SharedService {
+		SharedService {
+			inner: Box::new(stub),
+			meta: Arc::new(SvcMeta {
+				team_id: team.into(),
+				vm_id: "box".into(),
+				svc_id: id.into(),
+			}),
+			depends_on: depends_on.map(Into::into),
+			dependency: None,
+		}
+	}
+
+	fn linked(
+		services: &[(&str, &str, Option<&str>)],
+	) -> Result<Vec<Option<usize>>> {
+		let mut services = services
+			.iter()
+			.map(|&(team, id, dep)| service(team, id, dep, Stub::default()))
+			.collect::<Vec<_>>();
+		link_dependencies(&mut services)?;
+		Ok(services.iter().map(|svc| svc.dependency).collect())
+	}
+
+	// runs one round and returns each service's reason, in service order
+	fn round(services: &[SharedService]) -> Vec<Option<String>> {
+		let (tx, mut rx) = mpsc::unbounded_channel();
+		let mut polls = vec![None; services.len()];
+		let all = (0..services.len())
+			.map(|i| {
+				schedule(
+					services,
+					i,
+					&mut polls,
+					&tx,
+					Duration::from_secs(1),
+					1,
+				)
+			})
+			.collect::<Vec<_>>();
+		drop(tx);
+
+		Runtime::new().unwrap().block_on(async {
+			future::join_all(all).await;
+			let mut reasons = vec![None; services.len()];
+			while let Some(msg) = rx.recv().await {
+				let i = services
+					.iter()
+					.position(|svc| svc.meta.svc_id == msg.meta.svc_id)
+					.unwrap();
+				reasons[i] = Some(msg.outcome.reason().unwrap_or_default());
+			}
+			reasons
+		})
+	}
+
+	#[test]
+	fn links_within_the_same_team() {
+		let dependencies = linked(&[
+			("t1", "web", None),
+			("t1", "db", Some("web")),
+			("t2", "web", None),
+			("t2", "db", Some("web")),
+		]);
+		assert_eq!(dependencies.unwrap(), vec![None, Some(0), None, Some(2)]);
+	}
+
+	#[test]
+	fn rejects_unknown_and_other_teams_services() {
+		let unknown = linked(&[("t1", "db", Some("web"))]).unwrap_err();
+		assert!(unknown.to_string().contains("unknown service web"));
+		assert!(
+			linked(&[("t1", "web", None), ("t2", "db", Some("web"))]).is_err()
+		);
+	}
+
+	#[test]
+	fn rejects_cycles() {
+		assert!(linked(&[("t1", "a", Some("a"))]).is_err());
+		let cycle = linked(&[
+			("t1", "a", Some("c")),
+			("t1", "b", Some("a")),
+			("t1", "c", Some("b")),
+		]);
+		assert!(cycle.unwrap_err().to_string().contains("circular"));
+	}
+
+	#[test]
+	fn skips_dependents_of_down_services() {
+		let (b, c) = (Stub::default(), Stub::default());
+		let (b_probes, c_probes) = (b.probes.clone(), c.probes.clone());
+		let mut services = vec![
+			service(
+				"t1",
+				"a",
+				None,
+				Stub {
+					down: true,
+					..Stub::default()
+				},
+			),
+			service("t1", "b", Some("a"), b),
+			service("t1", "c", Some("b"), c),
+		];
+		link_dependencies(&mut services).unwrap();
+
+		assert_eq!(
+			round(&services),
+			vec![
+				Some("down".into()),
+				Some("dependency a down".into()),
+				Some("dependency b down".into()),
+			]
+		);
+		assert_eq!(b_probes.load(Ordering::SeqCst), 0);
+		assert_eq!(c_probes.load(Ordering::SeqCst), 0);
+	}
+
+	#[test]
+	fn polls_shared_dependencies_once() {
+		let a = Stub::default();
+		let a_probes = a.probes.clone();
+		let mut services = vec![
+			service("t1", "b", Some("a"), Stub::default()),
+			service("t1", "c", Some("a"), Stub::default()),
+			service("t1", "a", None, a),
+		];
+		link_dependencies(&mut services).unwrap();
+
+		assert_eq!(round(&services), vec![Some("".into()); 3]);
+		assert_eq!(a_probes.load(Ordering::SeqCst), 1);
 	}
 }
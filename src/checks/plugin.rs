@@ -0,0 +1,359 @@
+use super::{tls, Service};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::{
+	cell::RefCell,
+	fmt,
+	io::{self, Read, Write},
+	net::{Ipv4Addr, SocketAddrV4, TcpStream},
+	path::PathBuf,
+	rc::Rc,
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::oneshot, task, time};
+use tokio_rustls::rustls::{ClientSession, StreamOwned};
+use wasmtime::{
+	Caller, Config, Engine, Extern, InterruptHandle, Linker, Memory, Module,
+	Store, StoreLimitsBuilder, Trap,
+};
+
+// The whole host API. Plugins get no WASI, so this is everything they can do:
+//
+//   scylla.connect(port: i32, tls: i32) -> i32   socket handle, or -1
+//   scylla.send(fd: i32, ptr: i32, len: i32) -> i32   bytes sent, or -1
+//   scylla.recv(fd: i32, ptr: i32, len: i32) -> i32   bytes read, or -1; reads
+//     at most 64 KiB per call
+//   scylla.close(fd: i32)
+//   scylla.now_ms() -> i64   milliseconds since the unix epoch
+//   scylla.md5(ptr: i32, len: i32, out: i32)   writes 16 bytes to `out`
+//   scylla.sha256(ptr: i32, len: i32, out: i32)   writes 32 bytes to `out`
+//   scylla.fail(ptr: i32, len: i32)   sets the failure reason
+//
+// Modules export their `memory` and `check(port: i32) -> i32`, which returns 0
+// when the service is up. Connections only ever go to the box being checked.
+const HOST_MODULE: &str = "scylla";
+
+// most `recv` reads in one call, however big a buffer the plugin offers
+const MAX_RECV: usize = 64 * 1024;
+
+// 64 KiB pages, so plugins get at most 64 MiB of memory
+const MAX_MEMORY_PAGES: u32 = 1024;
+
+// Compiled modules by path. Every team and box runs the same plugin, so it's
+// only compiled once, with one engine shared between all of them.
+static MODULES: Mutex<Vec<(PathBuf, Module)>> = Mutex::new(Vec::new());
+
+enum Socket {
+	Plain(TcpStream),
+	Tls(Box<StreamOwned<ClientSession, TcpStream>>),
+}
+
+impl Socket {
+	fn connect(
+		remote: SocketAddrV4,
+		tls: bool,
+		timeout: Duration,
+	) -> Result<Self> {
+		let stream = TcpStream::connect_timeout(&remote.into(), timeout)?;
+		stream.set_read_timeout(Some(timeout))?;
+		stream.set_write_timeout(Some(timeout))?;
+		if !tls {
+			return Ok(Socket::Plain(stream));
+		}
+
+		let session = ClientSession::new(
+			&Arc::new(tls::insecure_client_config()),
+			tls::placeholder_domain(),
+		);
+		Ok(Socket::Tls(Box::new(StreamOwned::new(session, stream))))
+	}
+
+	fn send(&mut self, data: &[u8]) -> io::Result<usize> {
+		match self {
+			Socket::Plain(s) => s.write(data),
+			Socket::Tls(s) => s.write(data),
+		}
+	}
+
+	fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		match self {
+			Socket::Plain(s) => s.read(buf),
+			Socket::Tls(s) => s.read(buf),
+		}
+	}
+}
+
+struct State {
+	ip: Ipv4Addr,
+	timeout: Duration,
+	sockets: Vec<Option<Socket>>,
+	// set by `fail`, or by the last host call that went wrong
+	reason: Option<String>,
+}
+
+impl State {
+	fn socket(&mut self, fd: i32) -> Option<&mut Socket> {
+		self.sockets.get_mut(fd as usize)?.as_mut()
+	}
+}
+
+fn memory(caller: &Caller<'_>) -> Result<Memory, Trap> {
+	caller
+		.get_export("memory")
+		.and_then(Extern::into_memory)
+		.ok_or_else(|| Trap::new("Plugin does not export its memory"))
+}
+
+// Validates a plugin supplied range against the module's memory, so nothing
+// gets allocated for a length the plugin couldn't have filled in the first
+// place.
+fn mem_range(
+	memory: &Memory,
+	ptr: i32,
+	len: i32,
+) -> Result<(usize, usize), Trap> {
+	if ptr < 0 || len < 0 {
+		return Err(Trap::new("Plugin passed an out of bounds pointer"));
+	}
+	let (ptr, len) = (ptr as usize, len as usize);
+	match ptr.checked_add(len) {
+		Some(end) if end <= memory.data_size() => Ok((ptr, len)),
+		_ => Err(Trap::new("Plugin passed an out of bounds pointer")),
+	}
+}
+
+fn read_mem(caller: &Caller<'_>, ptr: i32, len: i32) -> Result<Vec<u8>, Trap> {
+	let memory = memory(caller)?;
+	let (ptr, len) = mem_range(&memory, ptr, len)?;
+	let mut buf = vec![0; len];
+	memory
+		.read(ptr, &mut buf)
+		.map_err(|_| Trap::new("Plugin passed an out of bounds pointer"))?;
+	Ok(buf)
+}
+
+fn write_mem(caller: &Caller<'_>, ptr: i32, data: &[u8]) -> Result<(), Trap> {
+	memory(caller)?
+		.write(ptr as usize, data)
+		.map_err(|_| Trap::new("Plugin passed an out of bounds pointer"))
+}
+
+fn link(linker: &mut Linker, state: &Rc<RefCell<State>>) -> Result<()> {
+	let s = state.clone();
+	linker.func(HOST_MODULE, "connect", move |port: i32, tls: i32| {
+		let mut state = s.borrow_mut();
+		let remote = SocketAddrV4::new(state.ip, port as u16);
+		match Socket::connect(remote, tls != 0, state.timeout) {
+			Ok(socket) => {
+				state.sockets.push(Some(socket));
+				state.sockets.len() as i32 - 1
+			}
+			Err(e) => {
+				state.reason = Some(format!("{:#}", e));
+				-1
+			}
+		}
+	})?;
+
+	let s = state.clone();
+	linker.func(
+		HOST_MODULE,
+		"send",
+		move |caller: Caller<'_>, fd: i32, ptr: i32, len: i32| {
+			let data = read_mem(&caller, ptr, len)?;
+			let mut state = s.borrow_mut();
+			let res = match state.socket(fd) {
+				Some(socket) => socket.send(&data),
+				None => return Ok(-1),
+			};
+			Ok(res.map(|n| n as i32).unwrap_or_else(|e| {
+				state.reason = Some(e.to_string());
+				-1
+			}))
+		},
+	)?;
+
+	let s = state.clone();
+	linker.func(
+		HOST_MODULE,
+		"recv",
+		move |caller: Caller<'_>, fd: i32, ptr: i32, len: i32| {
+			let (_, len) = mem_range(&memory(&caller)?, ptr, len)?;
+			let mut buf = vec![0; len.min(MAX_RECV)];
+			let mut state = s.borrow_mut();
+			let res = match state.socket(fd) {
+				Some(socket) => socket.recv(&mut buf),
+				None => return Ok(-1),
+			};
+			match res {
+				Ok(n) => {
+					write_mem(&caller, ptr, &buf[..n])?;
+					Ok(n as i32)
+				}
+				Err(e) => {
+					state.reason = Some(e.to_string());
+					Ok(-1)
+				}
+			}
+		},
+	)?;
+
+	let s = state.clone();
+	linker.func(HOST_MODULE, "close", move |fd: i32| {
+		if let Some(socket) = s.borrow_mut().sockets.get_mut(fd as usize) {
+			socket.take();
+		}
+	})?;
+
+	linker.func(HOST_MODULE, "now_ms", || {
+		SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map_or(0, |d| d.as_millis() as i64)
+	})?;
+
+	linker.func(
+		HOST_MODULE,
+		"md5",
+		|caller: Caller<'_>, ptr: i32, len: i32, out: i32| {
+			let digest = md5::compute(read_mem(&caller, ptr, len)?);
+			write_mem(&caller, out, &digest.0)
+		},
+	)?;
+
+	linker.func(
+		HOST_MODULE,
+		"sha256",
+		|caller: Caller<'_>, ptr: i32, len: i32, out: i32| {
+			let digest = Sha256::digest(&read_mem(&caller, ptr, len)?);
+			write_mem(&caller, out, &digest)
+		},
+	)?;
+
+	let s = state.clone();
+	linker.func(
+		HOST_MODULE,
+		"fail",
+		move |caller: Caller<'_>, ptr: i32, len: i32| {
+			let reason = read_mem(&caller, ptr, len)?;
+			s.borrow_mut().reason =
+				Some(String::from_utf8_lossy(&reason).into_owned());
+			Ok(())
+		},
+	)?;
+
+	Ok(())
+}
+
+// stops the plugin if the check is abandoned, since the blocking thread can't
+// be cancelled any other way
+struct Interrupt(InterruptHandle);
+
+impl Drop for Interrupt {
+	fn drop(&mut self) { self.0.interrupt() }
+}
+
+pub struct PluginCheck {
+	pub path: PathBuf,
+	pub module: Module,
+	pub ip: Ipv4Addr,
+	pub port: Option<u16>,
+	pub timeout: Duration,
+}
+
+impl PluginCheck {
+	pub fn load(
+		path: PathBuf,
+		ip: Ipv4Addr,
+		port: Option<u16>,
+		timeout: Duration,
+	) -> Result<Self> {
+		let mut modules = MODULES.lock().unwrap();
+		let module = match modules.iter().find(|(p, _)| *p == path) {
+			Some((_, module)) => module.clone(),
+			None => {
+				let engine = match modules.first() {
+					Some((_, module)) => module.engine().clone(),
+					None => Engine::new(Config::new().interruptable(true))?,
+				};
+				let module = Module::from_file(&engine, &path)?;
+				modules.push((path.clone(), module.clone()));
+				module
+			}
+		};
+		drop(modules);
+
+		Ok(Self {
+			path,
+			module,
+			ip,
+			port,
+			timeout,
+		})
+	}
+}
+
+impl fmt::Debug for PluginCheck {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("PluginCheck")
+			.field("path", &self.path)
+			.field("ip", &self.ip)
+			.field("port", &self.port)
+			.finish()
+	}
+}
+
+#[async_trait]
+impl Service for PluginCheck {
+	async fn is_up(&self) -> Result<()> {
+		let module = self.module.clone();
+		let (ip, port, timeout) = (self.ip, self.port, self.timeout);
+		let (tx, rx) = oneshot::channel();
+
+		// `Store` isn't `Send`, so everything wasm happens on one thread
+		let task = task::spawn_blocking(move || -> Result<()> {
+			// one instance with one memory and table, so the caps below can't
+			// be sidestepped by instantiating more
+			let limits = StoreLimitsBuilder::new()
+				.memory_pages(MAX_MEMORY_PAGES)
+				.table_elements(10_000)
+				.instances(1)
+				.memories(1)
+				.tables(1)
+				.build();
+			let store = Store::new_with_limits(module.engine(), limits);
+			let _ = tx.send(store.interrupt_handle()?);
+
+			let state = Rc::new(RefCell::new(State {
+				ip,
+				timeout,
+				sockets: Vec::new(),
+				reason: None,
+			}));
+			let mut linker = Linker::new(&store);
+			link(&mut linker, &state)?;
+
+			let code = linker
+				.instantiate(&module)?
+				.get_typed_func::<i32, i32>("check")?
+				.call(port.map_or(0, i32::from))?;
+			if code != 0 {
+				let reason = state.borrow_mut().reason.take();
+				bail!(reason
+					.unwrap_or_else(|| format!("Plugin returned {}", code)))
+			}
+			Ok(())
+		});
+
+		let _interrupt = match rx.await {
+			Ok(handle) => Interrupt(handle),
+			// the task failed before it got to running anything
+			Err(_) => return task.await?,
+		};
+		match time::timeout(self.timeout, task).await {
+			Ok(res) => res?,
+			Err(_) => Err(anyhow!("Plugin timed out")),
+		}
+	}
+}
@@ -0,0 +1,46 @@
+use super::{probe, ChanMsg, Outcome, Service, SvcMeta};
+use anyhow::Result;
+use async_trait::async_trait;
+use rand::seq::SliceRandom;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc::UnboundedSender;
+
+// One copy of a check per account in a team's credential pool. Each poll goes
+// through a random one, so teams can't get away with protecting a single user.
+#[derive(Debug)]
+pub struct PooledCheck {
+	// never empty, keyed by username
+	pub accounts: Vec<(String, Box<dyn Service>)>,
+}
+
+impl PooledCheck {
+	fn pick(&self) -> &(String, Box<dyn Service>) {
+		self.accounts
+			.choose(&mut rand::thread_rng())
+			.expect("credential pool is empty")
+	}
+}
+
+#[async_trait]
+impl Service for PooledCheck {
+	async fn is_up(&self) -> Result<()> { self.pick().1.is_up().await }
+
+	async fn poll(
+		&self,
+		chan: UnboundedSender<ChanMsg>,
+		timeout: Duration,
+		cx: Arc<SvcMeta>,
+		round: i32,
+	) -> Result<Outcome> {
+		let (username, check) = self.pick();
+		probe(
+			check.is_up(),
+			&chan,
+			timeout,
+			cx,
+			round,
+			Some(username.clone()),
+		)
+		.await
+	}
+}
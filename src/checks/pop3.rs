@@ -0,0 +1,109 @@
+use super::{conn::LineConn, Service};
+use crate::config::OutputMatch;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::net::SocketAddrV4;
+
+async fn expect_ok(conn: &mut LineConn) -> Result<String> {
+	let line = conn.read_line().await?;
+	if !line.starts_with("+OK") {
+		bail!("Unexpected reply from POP3 server: {}", line)
+	}
+	Ok(line)
+}
+
+// multi-line responses end with a lone `.`, and any other line starting with
+// one has had an extra `.` stuffed in front of it
+async fn read_multiline(conn: &mut LineConn) -> Result<Vec<String>> {
+	let mut lines = Vec::new();
+	loop {
+		let line = conn.read_line().await?;
+		match line.as_str() {
+			"." => return Ok(lines),
+			_ if line.starts_with('.') => lines.push(line[1..].to_owned()),
+			_ => lines.push(line),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct Pop3Check {
+	pub remote: SocketAddrV4,
+	// implicit TLS, usually on 995
+	pub tls: bool,
+	pub starttls: bool,
+	pub username: String,
+	pub password: String,
+	pub expect: Option<OutputMatch>,
+}
+
+impl Pop3Check {
+	// Logs in and lists the mailbox. If `expect` is given, messages are
+	// retrieved newest first until one of them matches, which `delete` then
+	// removes.
+	pub async fn search(
+		&self,
+		expect: Option<&OutputMatch>,
+		delete: bool,
+	) -> Result<()> {
+		let mut conn = LineConn::connect(self.remote, self.tls).await?;
+		expect_ok(&mut conn).await?;
+
+		if self.starttls {
+			conn.write_line("STLS").await?;
+			expect_ok(&mut conn).await?;
+			conn = conn.upgrade().await?;
+		}
+
+		conn.write_line(&format!("USER {}", self.username)).await?;
+		expect_ok(&mut conn).await?;
+		conn.write_line(&format!("PASS {}", self.password)).await?;
+		expect_ok(&mut conn).await?;
+
+		conn.write_line("LIST").await?;
+		expect_ok(&mut conn).await?;
+		let ids = read_multiline(&mut conn)
+			.await?
+			.iter()
+			.filter_map(|l| l.split_whitespace().next()?.parse::<u32>().ok())
+			.collect::<Vec<_>>();
+
+		if let Some(expect) = expect {
+			let mut found = false;
+			for id in ids.iter().rev() {
+				conn.write_line(&format!("RETR {}", id)).await?;
+				expect_ok(&mut conn).await?;
+				if expect
+					.is_match(&read_multiline(&mut conn).await?.join("\r\n"))
+				{
+					if delete {
+						conn.write_line(&format!("DELE {}", id)).await?;
+						expect_ok(&mut conn).await?;
+					}
+					found = true;
+					break;
+				}
+			}
+
+			if !found {
+				bail!("No message in the mailbox was {}", expect)
+			}
+		}
+
+		if delete {
+			// deletions only take effect once the server has acknowledged QUIT
+			conn.write_line("QUIT").await?;
+			expect_ok(&mut conn).await?;
+		} else {
+			let _ = conn.write_line("QUIT").await;
+		}
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl Service for Pop3Check {
+	async fn is_up(&self) -> Result<()> {
+		self.search(self.expect.as_ref(), false).await
+	}
+}
@@ -0,0 +1,193 @@
+use super::{composite::CompositeCheck, pool::PooledCheck, Service};
+use crate::config::{
+	builtin_check, check_budget, CompositeConfig, ServiceConfig, Team, Vm,
+};
+use anyhow::{bail, Context as _, Result};
+use std::{
+	collections::{HashMap, HashSet},
+	fmt,
+	time::Duration,
+};
+
+// The last argument is the poll timeout, which anything the check waits on by
+// itself has to fit inside.
+pub type CheckConstructor = Box<
+	dyn Fn(
+			&ServiceConfig,
+			(&String, &Team),
+			(&String, &Vm),
+			Duration,
+		) -> Result<Box<dyn Service>>
+		+ Send
+		+ Sync,
+>;
+
+// every `type` scylla understands out of the box
+const BUILTIN_CHECKS: &[&str] = &[
+	"tcp",
+	"tcpScript",
+	"udp",
+	"icmp",
+	"ssh",
+	"http",
+	"https",
+	"httpScenario",
+	"dns",
+	"ftp",
+	"smb",
+	"smtp",
+	"pop3",
+	"imap",
+	"mail",
+	"mysql",
+	"postgres",
+	"ldap",
+	"exec",
+	"script",
+	"plugin",
+];
+
+// built-in types that read `username` and `password` from their table, and so
+// can take them from a team's credential pool instead
+const POOLED_CHECKS: &[&str] = &[
+	"ssh", "ftp", "smb", "smtp", "pop3", "imap", "mysql", "postgres",
+];
+
+// Maps a service's `type` to whatever builds its check. Crates using scylla as
+// a library can register their own types on `Cfg::registry` before calling
+// `Cfg::set_services`.
+pub struct CheckRegistry {
+	constructors: HashMap<String, CheckConstructor>,
+	pooled: HashSet<String>,
+}
+
+impl CheckRegistry {
+	// replaces any earlier constructor for `ty`, built-ins included, which also
+	// takes away its use of credential pools until `allow_credentials` is
+	// called again
+	pub fn register<F>(&mut self, ty: &str, constructor: F)
+	where
+		F: Fn(
+				&ServiceConfig,
+				(&String, &Team),
+				(&String, &Vm),
+				Duration,
+			) -> Result<Box<dyn Service>>
+			+ Send
+			+ Sync
+			+ 'static,
+	{
+		self.constructors
+			.insert(ty.to_owned(), Box::new(constructor));
+		self.pooled.remove(ty);
+	}
+
+	// lets services of type `ty` name a credential pool, whose accounts get
+	// passed to the constructor as `username` and `password` in the table
+	pub fn allow_credentials(&mut self, ty: &str) {
+		self.pooled.insert(ty.to_owned());
+	}
+
+	pub fn build(
+		&self,
+		svc: &ServiceConfig,
+		team: (&String, &Team),
+		vm: (&String, &Vm),
+		timeout: Duration,
+	) -> Result<Box<dyn Service>> {
+		if let Some(ref pool) = svc.credentials {
+			if !self.pooled.contains(&svc.ty) {
+				bail!(
+					"Service {} uses credential pool {}, but {} checks don't \
+					 take a username and password",
+					svc.id,
+					pool,
+					svc.ty
+				)
+			}
+			let accounts = match team.1.credentials.get(pool) {
+				Some(accounts) if !accounts.is_empty() => accounts,
+				_ => bail!(
+					"Team {} has no credentials in pool {} for service {}",
+					team.0,
+					pool,
+					svc.id
+				),
+			};
+
+			// every account gets its own check, built as if its `username` and
+			// `password` had been written into the service's table
+			return Ok(Box::new(PooledCheck {
+				accounts: accounts
+					.iter()
+					.map(|account| {
+						let mut svc = svc.clone();
+						svc.credentials = None;
+						svc.table.insert(
+							"username".into(),
+							account.username.clone().into(),
+						);
+						svc.table.insert(
+							"password".into(),
+							account.password.clone().into(),
+						);
+						Ok((
+							account.username.clone(),
+							self.build(&svc, team, vm, timeout)?,
+						))
+					})
+					.collect::<Result<_>>()?,
+			}));
+		}
+
+		match self.constructors.get(&svc.ty) {
+			Some(constructor) => constructor(svc, team, vm, timeout),
+			// built here rather than registered so children can be of any
+			// registered type
+			None if svc.ty == "composite" => {
+				let composite = svc.parse::<CompositeConfig>()?;
+				// children run inside the composite's own poll
+				let budget = check_budget(timeout);
+				let children = composite
+					.checks
+					.iter()
+					.map(|child| {
+						Ok((
+							child.id.clone(),
+							self.build(child, team, vm, budget)?,
+						))
+					})
+					.collect::<Result<_>>()?;
+				Ok(Box::new(
+					CompositeCheck::new(composite.mode, children, budget)
+						.with_context(|| {
+							format!("Invalid composite {}", svc.id)
+						})?,
+				))
+			}
+			None => bail!("Service {} has unknown type {}", svc.id, svc.ty),
+		}
+	}
+}
+
+impl Default for CheckRegistry {
+	fn default() -> Self {
+		let mut registry = Self {
+			constructors: HashMap::new(),
+			pooled: HashSet::new(),
+		};
+		for ty in BUILTIN_CHECKS {
+			registry.register(ty, builtin_check);
+		}
+		for ty in POOLED_CHECKS {
+			registry.allow_credentials(ty);
+		}
+		registry
+	}
+}
+
+impl fmt::Debug for CheckRegistry {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_set().entries(self.constructors.keys()).finish()
+	}
+}
@@ -0,0 +1,232 @@
+use super::{conn::LineConn, Service};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use sha2::{Digest, Sha256};
+use std::{
+	future::Future,
+	net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+	path::PathBuf,
+	str::FromStr,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{
+	runtime::Handle,
+	task,
+	time::{self, Instant},
+};
+use trust_dns_proto::{rr::RecordType, xfer::DnsRequestOptions};
+use trust_dns_resolver::{
+	config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+	TokioAsyncResolver,
+};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+// Scripts run on a blocking thread, so every host API drives its future to
+// completion on the runtime and gives up once the check's deadline passes.
+#[derive(Clone)]
+struct Host {
+	handle: Handle,
+	deadline: Instant,
+}
+
+impl Host {
+	fn run<T>(&self, fut: impl Future<Output = Result<T>>) -> ScriptResult<T> {
+		self.handle
+			.block_on(time::timeout_at(self.deadline, fut))
+			.map_err(|_| "Script timed out")?
+			.map_err(|e| format!("{:#}", e).into())
+	}
+}
+
+#[derive(Clone)]
+struct TcpConn {
+	host: Host,
+	conn: Arc<Mutex<LineConn>>,
+}
+
+impl TcpConn {
+	fn open(host: &Host, ip: &str, port: i64, tls: bool) -> ScriptResult<Self> {
+		let remote = SocketAddrV4::new(
+			Ipv4Addr::from_str(ip).map_err(|e| e.to_string())?,
+			port as u16,
+		);
+		Ok(Self {
+			host: host.clone(),
+			conn: Arc::new(Mutex::new(
+				host.run(LineConn::connect(remote, tls))?,
+			)),
+		})
+	}
+
+	fn read_line(&mut self) -> ScriptResult<String> {
+		let mut conn = self.conn.lock().unwrap();
+		self.host.run(conn.read_line())
+	}
+
+	fn read(&mut self, len: i64) -> ScriptResult<String> {
+		let mut conn = self.conn.lock().unwrap();
+		let data = self.host.run(conn.read_exact(len as usize))?;
+		Ok(String::from_utf8_lossy(&data).into_owned())
+	}
+
+	fn write(&mut self, data: &str) -> ScriptResult<()> {
+		let mut conn = self.conn.lock().unwrap();
+		self.host.run(conn.write_all(data.as_bytes()))
+	}
+
+	fn write_line(&mut self, line: &str) -> ScriptResult<()> {
+		let mut conn = self.conn.lock().unwrap();
+		self.host.run(conn.write_line(line))
+	}
+}
+
+async fn http(method: &str, url: &str, body: Option<String>) -> Result<Map> {
+	let client = reqwest::Client::builder()
+		.danger_accept_invalid_certs(true)
+		.build()?;
+	let mut req = client.request(reqwest::Method::from_str(method)?, url);
+	if let Some(body) = body {
+		req = req.body(body);
+	}
+	let res = req.send().await?;
+
+	let mut map = Map::new();
+	map.insert("status".into(), (res.status().as_u16() as i64).into());
+	map.insert("body".into(), res.text().await?.into());
+	Ok(map)
+}
+
+async fn dns_lookup(server: &str, name: &str, ty: &str) -> Result<Array> {
+	let mut config = ResolverConfig::new();
+	config.add_name_server(NameServerConfig {
+		socket_addr: SocketAddr::new(server.parse()?, 53),
+		protocol: Protocol::Udp,
+		tls_dns_name: None,
+		trust_nx_responses: true,
+		tls_config: None,
+	});
+
+	let resolver = TokioAsyncResolver::tokio(
+		config,
+		ResolverOpts {
+			cache_size: 0,
+			use_hosts_file: false,
+			..ResolverOpts::default()
+		},
+	)?;
+	let lookup = resolver
+		.lookup(
+			name,
+			RecordType::from_str(&ty.to_uppercase())?,
+			DnsRequestOptions::default(),
+		)
+		.await?;
+	Ok(lookup.iter().map(|r| r.to_string().into()).collect())
+}
+
+fn build_engine(host: Host) -> Engine {
+	let mut engine = Engine::new();
+	let deadline = host.deadline;
+	engine.on_progress(move |_| {
+		if Instant::now() >= deadline {
+			Some("Script timed out".into())
+		} else {
+			None
+		}
+	});
+
+	engine
+		.register_type_with_name::<TcpConn>("TcpConn")
+		.register_fn("read_line", TcpConn::read_line)
+		.register_fn("read", TcpConn::read)
+		.register_fn("write", TcpConn::write)
+		.register_fn("write_line", TcpConn::write_line);
+
+	let h = host.clone();
+	engine.register_fn("tcp_connect", move |ip: &str, port: i64| {
+		TcpConn::open(&h, ip, port, false)
+	});
+	let h = host.clone();
+	engine.register_fn("tls_connect", move |ip: &str, port: i64| {
+		TcpConn::open(&h, ip, port, true)
+	});
+
+	let h = host.clone();
+	engine.register_fn("http_get", move |url: &str| {
+		h.run(http("GET", url, None))
+	});
+	let h = host.clone();
+	engine.register_fn("http_post", move |url: &str, body: &str| {
+		h.run(http("POST", url, Some(body.to_owned())))
+	});
+
+	engine.register_fn(
+		"dns_lookup",
+		move |server: &str, name: &str, ty: &str| {
+			host.run(dns_lookup(server, name, ty))
+		},
+	);
+
+	engine
+		.register_fn("md5", |data: &str| format!("{:x}", md5::compute(data)))
+		.register_fn("sha256", |data: &str| {
+			hex::encode(Sha256::digest(data.as_bytes()))
+		});
+
+	engine
+}
+
+// Runs a Rhai script with `team`, `box`, `ip` and `port` in scope. The service
+// is up if the script evaluates to `true` or nothing at all, and down if it
+// evaluates to `false` or throws, with whatever it threw as the reason. The
+// file is read on every poll so scripts can be edited while scylla runs.
+#[derive(Debug)]
+pub struct ScriptCheck {
+	pub path: PathBuf,
+	pub team_id: String,
+	pub vm_id: String,
+	pub ip: Ipv4Addr,
+	pub port: Option<u16>,
+	pub timeout: Duration,
+}
+
+#[async_trait]
+impl Service for ScriptCheck {
+	async fn is_up(&self) -> Result<()> {
+		let host = Host {
+			handle: Handle::current(),
+			deadline: Instant::now() + self.timeout,
+		};
+		let path = self.path.clone();
+		let mut scope = Scope::new();
+		scope
+			.push_constant("team", self.team_id.clone())
+			.push_constant("box", self.vm_id.clone())
+			.push_constant("ip", self.ip.to_string())
+			.push_constant("port", self.port.map_or(0, i64::from));
+
+		let result = task::spawn_blocking(move || {
+			let engine = build_engine(host);
+			let ast = engine.compile_file(path)?;
+			engine.eval_ast_with_scope::<Dynamic>(&mut scope, &ast)
+		})
+		.await?;
+
+		match result {
+			Ok(value) if value.is_unit() => Ok(()),
+			Ok(value) => match value.as_bool() {
+				Ok(true) => Ok(()),
+				Ok(false) => bail!("Script reported the service down"),
+				Err(ty) => bail!("Script returned a {} instead of a bool", ty),
+			},
+			Err(e) => match *e {
+				EvalAltResult::ErrorRuntime(reason, _) => bail!("{}", reason),
+				EvalAltResult::ErrorTerminated(..) => bail!("Script timed out"),
+				e => Err(anyhow!("{}", e)),
+			},
+		}
+	}
+}
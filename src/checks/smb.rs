@@ -0,0 +1,157 @@
+use super::Service;
+use anyhow::{bail, Context as _, Result};
+use async_trait::async_trait;
+use std::{net::SocketAddrV4, process};
+use tokio::process::Command;
+
+// `smbclient --version` prints e.g. `Version 4.13.17-Ubuntu`
+fn major_version(version: &str) -> Option<u32> {
+	version
+		.trim()
+		.strip_prefix("Version ")?
+		.split('.')
+		.next()?
+		.parse()
+		.ok()
+}
+
+// A missing or ancient smbclient would score every team down for a problem on
+// the engine host, so it's checked once when the config is loaded instead.
+pub fn check_smbclient() -> Result<()> {
+	let output = process::Command::new("smbclient")
+		.arg("--version")
+		.output()
+		.context("Failed to run smbclient, which SMB checks need installed")?;
+	let version = String::from_utf8_lossy(&output.stdout);
+	match major_version(&version) {
+		// SMB2/3 support and `--max-protocol SMB3` arrived with Samba 4
+		Some(major) if major >= 4 => Ok(()),
+		_ => bail!(
+			"SMB checks need smbclient 4.0 or newer, found {:?}",
+			version.trim()
+		),
+	}
+}
+
+// `smbclient` splits commands on `;` and has no way to escape a `"` inside a
+// quoted argument, so paths containing either can't be passed through safely.
+pub fn get_command(path: &str) -> Result<String> {
+	if path.contains(|c| matches!(c, '"' | ';' | '\n' | '\r')) {
+		bail!(
+			"SMB path {:?} can't contain quotes, semicolons or newlines",
+			path
+		)
+	}
+	Ok(format!("get \"{}\" -", path))
+}
+
+// There's no usable SMB2/3 client crate, so this drives Samba's `smbclient`,
+// which needs to be installed on the engine host. NTLM negotiation and the
+// share/path handling all happen there; we only hash what it hands back.
+#[derive(Debug)]
+pub struct SmbCheck {
+	pub remote: SocketAddrV4,
+	pub username: String,
+	pub password: String,
+	pub domain: Option<String>,
+	pub share: String,
+	pub path: String,
+	pub content_hash: String,
+}
+
+#[async_trait]
+impl Service for SmbCheck {
+	async fn is_up(&self) -> Result<()> {
+		let mut cmd = Command::new("smbclient");
+		cmd.arg(format!("//{}/{}", self.remote.ip(), self.share))
+			.arg("--port")
+			.arg(self.remote.port().to_string())
+			.arg("--user")
+			.arg(&self.username)
+			.arg("--max-protocol")
+			.arg("SMB3")
+			.arg("--option")
+			.arg("client min protocol=SMB2")
+			.arg("--debuglevel")
+			.arg("0")
+			.arg("--command")
+			.arg(get_command(&self.path)?)
+			// keep the password out of the process list
+			.env("PASSWD", &self.password)
+			// the poll timeout drops this future, which must take the child
+			// down with it
+			.kill_on_drop(true);
+
+		if let Some(ref domain) = self.domain {
+			cmd.arg("--workgroup").arg(domain);
+		}
+
+		let output = cmd.output().await?;
+		if !output.status.success() {
+			bail!(
+				"smbclient exited with {}: {}",
+				output.status,
+				String::from_utf8_lossy(&output.stderr).trim()
+			)
+		}
+
+		let digest = md5::compute(&output.stdout);
+		if format!("{:x}", digest) != self.content_hash {
+			bail!("Hash comparison failed")
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::runtime::Runtime;
+
+	#[test]
+	fn parses_smbclient_versions() {
+		assert_eq!(major_version("Version 4.13.17-Ubuntu\n"), Some(4));
+		assert_eq!(major_version("Version 3.6.25"), Some(3));
+		assert_eq!(major_version("smbclient: not found"), None);
+	}
+
+	#[test]
+	fn quotes_plain_paths() {
+		assert_eq!(
+			get_command("dir/some file.txt").unwrap(),
+			"get \"dir/some file.txt\" -"
+		);
+	}
+
+	#[test]
+	fn rejects_paths_that_break_the_command() {
+		for path in &["a\"b", "a; rm b", "a\nget b", "a\rb"] {
+			assert!(get_command(path).is_err(), "{:?} was accepted", path);
+		}
+	}
+
+	// Needs smbclient and the `samba` service from docker-compose.yaml:
+	//   docker-compose up -d samba && cargo test -- --ignored
+	#[test]
+	#[ignore]
+	fn reads_file_from_samba() {
+		let content = include_bytes!("../../tests/fixtures/smb/flag.txt");
+		let mut check = SmbCheck {
+			remote: "127.0.0.1:1445".parse().unwrap(),
+			username: "scylla".into(),
+			password: "password".into(),
+			domain: None,
+			share: "public".into(),
+			path: "flag.txt".into(),
+			content_hash: format!("{:x}", md5::compute(&content[..])),
+		};
+
+		let rt = Runtime::new().unwrap();
+		// nothing but the file may end up on stdout for the hash to match
+		rt.block_on(check.is_up()).unwrap();
+
+		check.path = "missing.txt".into();
+		assert!(rt.block_on(check.is_up()).is_err());
+	}
+}
@@ -0,0 +1,123 @@
+use super::{conn::LineConn, Service};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use rand::{distributions::Alphanumeric, Rng};
+use std::net::SocketAddrV4;
+
+// Reads a (possibly multi-line) reply and fails unless its code is one of
+// `expected`. Returns the text of every line in the reply.
+async fn expect_reply(
+	conn: &mut LineConn,
+	expected: &[u16],
+) -> Result<Vec<String>> {
+	let mut lines = Vec::new();
+	loop {
+		let line = conn.read_line().await?;
+		let code = line.get(..3).and_then(|c| c.parse::<u16>().ok());
+		// `250-` continues a reply, `250 ` ends it
+		let last = line.as_bytes().get(3) != Some(&b'-');
+
+		match code {
+			Some(code) if last && expected.contains(&code) => {
+				lines.push(line);
+				return Ok(lines);
+			}
+			Some(_) if last => {
+				bail!("Unexpected reply from SMTP server: {}", line)
+			}
+			Some(_) => lines.push(line),
+			None => bail!("Malformed reply from SMTP server: {}", line),
+		}
+	}
+}
+
+pub fn nonce() -> String {
+	rand::thread_rng()
+		.sample_iter(&Alphanumeric)
+		.take(16)
+		.map(char::from)
+		.collect()
+}
+
+#[derive(Debug)]
+pub struct SmtpCheck {
+	pub remote: SocketAddrV4,
+	// implicit TLS, usually on 465
+	pub tls: bool,
+	pub starttls: bool,
+	pub username: Option<String>,
+	pub password: Option<String>,
+	pub from: String,
+	pub to: String,
+}
+
+impl SmtpCheck {
+	// submits a message with `nonce` in its subject and body
+	pub async fn send(&self, nonce: &str) -> Result<()> {
+		let mut conn = LineConn::connect(self.remote, self.tls).await?;
+		expect_reply(&mut conn, &[220]).await?;
+
+		conn.write_line("EHLO scylla.local").await?;
+		let mut capabilities = expect_reply(&mut conn, &[250]).await?;
+
+		if self.starttls {
+			conn.write_line("STARTTLS").await?;
+			expect_reply(&mut conn, &[220]).await?;
+			conn = conn.upgrade().await?;
+
+			// capabilities may differ once the channel is encrypted
+			conn.write_line("EHLO scylla.local").await?;
+			capabilities = expect_reply(&mut conn, &[250]).await?;
+		}
+
+		if let Some(ref username) = self.username {
+			if !capabilities.iter().any(|c| {
+				c.get(4..)
+					.map_or(false, |c| c.to_uppercase().starts_with("AUTH"))
+			}) {
+				bail!("SMTP server does not offer AUTH")
+			}
+
+			let credentials = format!(
+				"\0{}\0{}",
+				username,
+				self.password.as_deref().unwrap_or("")
+			);
+			conn.write_line(&format!(
+				"AUTH PLAIN {}",
+				base64::encode(credentials)
+			))
+			.await?;
+			expect_reply(&mut conn, &[235]).await?;
+		}
+
+		conn.write_line(&format!("MAIL FROM:<{}>", self.from))
+			.await?;
+		expect_reply(&mut conn, &[250]).await?;
+		conn.write_line(&format!("RCPT TO:<{}>", self.to)).await?;
+		expect_reply(&mut conn, &[250, 251]).await?;
+		conn.write_line("DATA").await?;
+		expect_reply(&mut conn, &[354]).await?;
+
+		let message = format!(
+			"From: <{from}>\r\nTo: <{to}>\r\nDate: {date}\r\nMessage-ID: \
+			 <{nonce}@scylla.local>\r\nSubject: scylla check \
+			 {nonce}\r\n\r\nscylla check {nonce}\r\n.\r\n",
+			from = self.from,
+			to = self.to,
+			date = Utc::now().to_rfc2822(),
+			nonce = nonce,
+		);
+		conn.write_all(message.as_bytes()).await?;
+		expect_reply(&mut conn, &[250]).await?;
+
+		let _ = conn.write_line("QUIT").await;
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl Service for SmtpCheck {
+	async fn is_up(&self) -> Result<()> { self.send(&nonce()).await }
+}
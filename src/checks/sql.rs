@@ -0,0 +1,106 @@
+use super::Service;
+use crate::config::{Credential, OutputMatch};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use sqlx::{
+	mysql::{MySqlConnectOptions, MySqlConnection, MySqlRow},
+	postgres::{PgConnectOptions, PgConnection, PgRow},
+	Connection, Row,
+};
+use std::net::SocketAddrV4;
+
+// Tries each type in turn until one decodes, since the checker has no idea
+// what the configured query returns.
+macro_rules! column_text {
+	($row:expr, $($ty:ty),+) => {{
+		let row = $row;
+		None
+			$(.or_else(|| {
+				let value = row.try_get::<Option<$ty>, _>(0).ok()?;
+				Some(value.map_or_else(|| "NULL".to_owned(), |v| v.to_string()))
+			}))+
+			.ok_or_else(|| anyhow!("Unsupported type in first column"))
+	}};
+}
+
+fn mysql_text(row: &MySqlRow) -> Result<String> {
+	column_text!(row, String, i64, u64, f64, bool)
+}
+
+fn pg_text(row: &PgRow) -> Result<String> {
+	column_text!(row, String, i64, i32, i16, f64, f32, bool)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SqlDriver {
+	Mysql,
+	Postgres,
+}
+
+#[derive(Debug)]
+pub struct SqlCheck {
+	pub remote: SocketAddrV4,
+	pub driver: SqlDriver,
+	pub login: Credential,
+	pub database: Option<String>,
+	pub query: String,
+	pub expect: Option<OutputMatch>,
+}
+
+impl SqlCheck {
+	// returns the first column of the first row, if there is one
+	async fn run_query(&self) -> Result<Option<String>> {
+		let host = self.remote.ip().to_string();
+		match self.driver {
+			SqlDriver::Mysql => {
+				let mut options = MySqlConnectOptions::new()
+					.host(&host)
+					.port(self.remote.port())
+					.username(&self.login.username)
+					.password(&self.login.password);
+				if let Some(ref database) = self.database {
+					options = options.database(database);
+				}
+
+				let mut conn = MySqlConnection::connect_with(&options).await?;
+				let row =
+					sqlx::query(&self.query).fetch_optional(&mut conn).await?;
+				let _ = conn.close().await;
+				row.as_ref().map(mysql_text).transpose()
+			}
+			SqlDriver::Postgres => {
+				let mut options = PgConnectOptions::new()
+					.host(&host)
+					.port(self.remote.port())
+					.username(&self.login.username)
+					.password(&self.login.password);
+				if let Some(ref database) = self.database {
+					options = options.database(database);
+				}
+
+				let mut conn = PgConnection::connect_with(&options).await?;
+				let row =
+					sqlx::query(&self.query).fetch_optional(&mut conn).await?;
+				let _ = conn.close().await;
+				row.as_ref().map(pg_text).transpose()
+			}
+		}
+	}
+}
+
+#[async_trait]
+impl Service for SqlCheck {
+	async fn is_up(&self) -> Result<()> {
+		let value = self.run_query().await?;
+		if let Some(ref expect) = self.expect {
+			match value {
+				Some(value) if expect.is_match(&value) => {}
+				Some(value) => {
+					bail!("Expected first column {}, got {}", expect, value)
+				}
+				None => bail!("Query returned no rows"),
+			}
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,100 @@
+use super::Service;
+use crate::config::OutputMatch;
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use ssh2::{HashType, Session};
+use std::{
+	io::Read,
+	net::{SocketAddrV4, TcpStream},
+	path::PathBuf,
+	time::Duration,
+};
+use tokio::task;
+
+#[derive(Debug, Clone)]
+pub enum SshAuth {
+	Password(String),
+	Key {
+		path: PathBuf,
+		passphrase: Option<String>,
+	},
+}
+
+#[derive(Debug, Clone)]
+pub struct SshCheck {
+	pub remote: SocketAddrV4,
+	pub username: String,
+	pub auth: SshAuth,
+	pub command: String,
+	pub expect: Option<OutputMatch>,
+	pub host_key: Option<String>,
+	pub timeout: Duration,
+}
+
+impl SshCheck {
+	// libssh2 is blocking, so this runs on its own thread
+	fn run(&self) -> Result<()> {
+		let tcp =
+			TcpStream::connect_timeout(&self.remote.into(), self.timeout)?;
+		let mut sess = Session::new()?;
+		sess.set_tcp_stream(tcp);
+		sess.set_timeout(self.timeout.as_millis() as u32);
+		sess.handshake()?;
+
+		if let Some(ref pinned) = self.host_key {
+			let hash = sess
+				.host_key_hash(HashType::Sha256)
+				.ok_or(anyhow!("Server did not present a host key"))?;
+			let fingerprint = format!(
+				"SHA256:{}",
+				base64::encode_config(hash, base64::STANDARD_NO_PAD)
+			);
+			if pinned.trim_start_matches("SHA256:")
+				!= fingerprint.trim_start_matches("SHA256:")
+			{
+				bail!("Host key mismatch: got {}", fingerprint)
+			}
+		}
+
+		match self.auth {
+			SshAuth::Password(ref password) => {
+				sess.userauth_password(&self.username, password)?
+			}
+			SshAuth::Key {
+				ref path,
+				ref passphrase,
+			} => sess.userauth_pubkey_file(
+				&self.username,
+				None,
+				path,
+				passphrase.as_deref(),
+			)?,
+		}
+		if !sess.authenticated() {
+			bail!("Failed to authenticate as {}", self.username)
+		}
+
+		let mut channel = sess.channel_session()?;
+		channel.exec(&self.command)?;
+
+		let mut stdout = String::new();
+		channel.read_to_string(&mut stdout)?;
+		channel.wait_close()?;
+
+		if let Some(ref expect) = self.expect {
+			if !expect.is_match(&stdout) {
+				bail!("Command output did not match: {:?}", stdout.trim())
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl Service for SshCheck {
+	async fn is_up(&self) -> Result<()> {
+		let check = self.clone();
+		task::spawn_blocking(move || check.run()).await?
+	}
+}
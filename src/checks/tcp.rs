@@ -1,8 +1,10 @@
-use super::Service;
-use anyhow::Result;
+use super::{conn::LineConn, Service};
+use crate::config::ScriptStep;
+use anyhow::{bail, Context as _, Result};
 use async_trait::async_trait;
-use std::net::SocketAddrV4;
-use tokio::net::TcpStream;
+use regex::Regex;
+use std::{net::SocketAddrV4, time::Duration};
+use tokio::{net::TcpStream, time};
 
 #[derive(Debug)]
 pub struct TcpCheck {
@@ -15,3 +17,47 @@ impl Service for TcpCheck {
 		Ok(TcpStream::connect(&self.remote).await.map(|_| ())?)
 	}
 }
+
+// Lines that don't match are skipped, so banners and other chatter don't need
+// their own steps.
+async fn expect_line(conn: &mut LineConn, re: &Regex) -> Result<()> {
+	loop {
+		if re.is_match(&conn.read_line().await?) {
+			return Ok(());
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct TcpScriptCheck {
+	pub remote: SocketAddrV4,
+	pub tls: bool,
+	pub steps: Vec<ScriptStep>,
+	// how long each expect waits for a matching line, kept shorter than the
+	// poll's timeout so a stuck step gets reported as such
+	pub step_timeout: Duration,
+}
+
+#[async_trait]
+impl Service for TcpScriptCheck {
+	async fn is_up(&self) -> Result<()> {
+		let mut conn = LineConn::connect(self.remote, self.tls).await?;
+
+		for (i, step) in self.steps.iter().enumerate() {
+			match step {
+				ScriptStep::Send(line) => conn.write_line(line).await,
+				ScriptStep::Expect(re) => time::timeout(
+					self.step_timeout,
+					expect_line(&mut conn, &re.0),
+				)
+				.await
+				.unwrap_or_else(|_| {
+					bail!("Never received a line matching /{}/", re.0)
+				}),
+			}
+			.with_context(|| format!("Step {} failed", i + 1))?;
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,208 @@
+use crate::config::TlsPolicy;
+use anyhow::{anyhow, bail, Context as _};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use std::{
+	fs::File, io::BufReader, net::SocketAddrV4, sync::Arc, time::SystemTime,
+};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+	rustls::{
+		Certificate, ClientConfig, RootCertStore, ServerCertVerified,
+		ServerCertVerifier, Session, TLSError,
+	},
+	webpki::{
+		self, DNSNameRef, EndEntityCert, SignatureAlgorithm,
+		TLSServerTrustAnchors, Time,
+	},
+	TlsConnector,
+};
+use x509_parser::{extensions::GeneralName, parse_x509_certificate};
+
+// Team boxes almost always serve self-signed certificates, so checks that only
+// need an encrypted channel accept whatever they're handed.
+struct AcceptAnyCert;
+
+impl ServerCertVerifier for AcceptAnyCert {
+	fn verify_server_cert(
+		&self,
+		_roots: &RootCertStore,
+		_presented_certs: &[Certificate],
+		_dns_name: DNSNameRef,
+		_ocsp_response: &[u8],
+	) -> Result<ServerCertVerified, TLSError> {
+		Ok(ServerCertVerified::assertion())
+	}
+}
+
+pub fn insecure_client_config() -> ClientConfig {
+	let mut config = ClientConfig::new();
+	config
+		.dangerous()
+		.set_certificate_verifier(Arc::new(AcceptAnyCert));
+	config
+}
+
+// webpki refuses IP addresses as server names, and we never verify the name
+// anyways
+pub fn placeholder_domain() -> DNSNameRef<'static> {
+	DNSNameRef::try_from_ascii_str("scylla.local").unwrap()
+}
+
+// same set rustls accepts by default
+static SUPPORTED_SIG_ALGS: &[&SignatureAlgorithm] = &[
+	&webpki::ECDSA_P256_SHA256,
+	&webpki::ECDSA_P256_SHA384,
+	&webpki::ECDSA_P384_SHA256,
+	&webpki::ECDSA_P384_SHA384,
+	&webpki::ED25519,
+	&webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+	&webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+	&webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+	&webpki::RSA_PKCS1_2048_8192_SHA256,
+	&webpki::RSA_PKCS1_2048_8192_SHA384,
+	&webpki::RSA_PKCS1_2048_8192_SHA512,
+	&webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+fn name_matches(pattern: &str, hostname: &str) -> bool {
+	let (pattern, hostname) = (pattern.to_lowercase(), hostname.to_lowercase());
+	match pattern.strip_prefix("*.") {
+		// wildcards only ever cover a single label
+		Some(suffix) => hostname
+			.split_once('.')
+			.map_or(false, |(_, rest)| rest == suffix),
+		None => pattern == hostname,
+	}
+}
+
+// Does its own handshake so the certificate can be judged separately from
+// whatever the protocol on top of it is doing. Every policy left unset is
+// skipped, so the default policy accepts any certificate.
+pub async fn verify_policy(
+	remote: SocketAddrV4,
+	policy: &TlsPolicy,
+) -> anyhow::Result<()> {
+	let domain = match policy.hostname {
+		Some(ref hostname) => DNSNameRef::try_from_ascii_str(hostname)
+			.unwrap_or_else(|_| placeholder_domain()),
+		None => placeholder_domain(),
+	};
+
+	let tcp = TcpStream::connect(remote).await?;
+	let stream = TlsConnector::from(Arc::new(insecure_client_config()))
+		.connect(domain, tcp)
+		.await
+		.context("TLS handshake failed")?;
+
+	let chain = stream
+		.get_ref()
+		.1
+		.get_peer_certificates()
+		.unwrap_or_default();
+	let leaf = chain
+		.first()
+		.ok_or(anyhow!("Server did not present a certificate"))?;
+
+	if let Some(ref pinned) = policy.fingerprint {
+		let fingerprint = hex::encode(Sha256::digest(&leaf.0));
+		if pinned.replace(':', "").to_lowercase() != fingerprint {
+			bail!("Certificate fingerprint mismatch: got {}", fingerprint)
+		}
+	}
+
+	if let Some(ref ca) = policy.ca {
+		let mut roots = RootCertStore::empty();
+		roots
+			.add_pem_file(&mut BufReader::new(File::open(ca)?))
+			.map_err(|_| anyhow!("Failed to parse CA file {}", ca.display()))?;
+		let anchors = roots
+			.roots
+			.iter()
+			.map(|root| root.to_trust_anchor())
+			.collect::<Vec<_>>();
+		let intermediates = chain[1..]
+			.iter()
+			.map(|cert| cert.0.as_slice())
+			.collect::<Vec<_>>();
+		let now = Time::try_from(SystemTime::now())
+			.map_err(|_| anyhow!("System clock is set before 1970"))?;
+
+		EndEntityCert::from(&leaf.0)
+			.and_then(|cert| {
+				cert.verify_is_valid_tls_server_cert(
+					SUPPORTED_SIG_ALGS,
+					&TLSServerTrustAnchors(&anchors),
+					&intermediates,
+					now,
+				)
+			})
+			.map_err(|e| {
+				anyhow!("Certificate does not chain to the trusted CA: {}", e)
+			})?;
+	}
+
+	let (_, cert) = parse_x509_certificate(&leaf.0)
+		.map_err(|e| anyhow!("Failed to parse certificate: {}", e))?;
+
+	if let Some(ref hostname) = policy.hostname {
+		let mut names = cert
+			.subject()
+			.iter_common_name()
+			.filter_map(|cn| cn.as_str().ok())
+			.collect::<Vec<_>>();
+		if let Ok(Some(san)) = cert.subject_alternative_name() {
+			names.extend(san.value.general_names.iter().filter_map(|name| {
+				match name {
+					GeneralName::DNSName(name) => Some(*name),
+					_ => None,
+				}
+			}));
+		}
+
+		if !names.iter().any(|name| name_matches(name, hostname)) {
+			bail!(
+				"Certificate is not valid for {}, only for: {}",
+				hostname,
+				names.join(", ")
+			)
+		}
+	}
+
+	if let Some(days) = policy.expiry_days {
+		let remaining =
+			cert.validity().not_after.timestamp() - Utc::now().timestamp();
+		if remaining < 0 {
+			bail!("Certificate has expired")
+		} else if remaining < days as i64 * 86400 {
+			bail!("Certificate expires in {} days", remaining / 86400)
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::name_matches;
+
+	#[test]
+	fn exact_names_ignore_case() {
+		assert!(name_matches("www.team.local", "WWW.Team.Local"));
+		assert!(!name_matches("www.team.local", "mail.team.local"));
+	}
+
+	#[test]
+	fn wildcards_cover_one_label() {
+		assert!(name_matches("*.team.local", "www.team.local"));
+		assert!(!name_matches("*.team.local", "a.www.team.local"));
+		assert!(!name_matches("*.team.local", "team.local"));
+		assert!(!name_matches("*.team.local", "www.other.local"));
+	}
+
+	#[test]
+	fn wildcards_only_count_as_a_whole_label() {
+		assert!(!name_matches("w*.team.local", "www.team.local"));
+		assert!(!name_matches("www.*.local", "www.team.local"));
+	}
+}
@@ -1,19 +1,58 @@
 use super::Service;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use async_trait::async_trait;
+use regex::Regex;
 use std::net::SocketAddrV4;
 use tokio::net::UdpSocket;
 
+#[derive(Debug)]
+pub enum UdpReply {
+	Bytes(Vec<u8>),
+	Regex(Regex),
+}
+
 #[derive(Debug)]
 pub struct UdpCheck {
 	pub remote: SocketAddrV4,
 	pub socket_addr: SocketAddrV4,
+	// with neither a payload nor an expected reply, the check only makes sure
+	// the socket can be set up, as it did before payloads existed
+	pub payload: Option<Vec<u8>>,
+	// any reply at all is good enough when unset
+	pub expect: Option<UdpReply>,
 }
 
 #[async_trait]
 impl Service for UdpCheck {
 	async fn is_up(&self) -> Result<()> {
 		let sock = UdpSocket::bind(&self.socket_addr).await?;
-		Ok(sock.connect(&self.remote).await?)
+		// connecting filters out datagrams from anyone but the remote, and
+		// surfaces ICMP port unreachable as an error on recv
+		sock.connect(&self.remote).await?;
+		let payload = match (&self.payload, &self.expect) {
+			(None, None) => return Ok(()),
+			(payload, _) => payload.as_deref().unwrap_or_default(),
+		};
+		sock.send(payload).await?;
+
+		let mut buf = vec![0; 65535];
+		let len = sock.recv(&mut buf).await?;
+		let reply = &buf[..len];
+
+		match self.expect {
+			Some(UdpReply::Bytes(ref expected)) if reply != &expected[..] => {
+				bail!(
+					"Expected reply {}, got {}",
+					hex::encode(expected),
+					hex::encode(reply)
+				)
+			}
+			Some(UdpReply::Regex(ref re))
+				if !re.is_match(&String::from_utf8_lossy(reply)) =>
+			{
+				bail!("Reply did not match /{}/: {}", re, hex::encode(reply))
+			}
+			_ => Ok(()),
+		}
 	}
 }
@@ -1,28 +1,48 @@
 use crate::checks::{
-	dns::DnsCheck, http::HttpCheck, tcp::TcpCheck, udp::UdpCheck, Service,
-	SvcMeta,
+	dns::{DnsCheck, DnsRecord},
+	exec::ExecCheck,
+	ftp::FtpCheck,
+	http::{HttpCheck, HttpScenarioCheck},
+	icmp::IcmpCheck,
+	imap::ImapCheck,
+	ldap::LdapCheck,
+	link_dependencies,
+	mail::{MailCheck, Mailbox},
+	plugin::PluginCheck,
+	pop3::Pop3Check,
+	registry::CheckRegistry,
+	script::ScriptCheck,
+	smb::{self, SmbCheck},
+	smtp::SmtpCheck,
+	sql::{SqlCheck, SqlDriver},
+	ssh::{SshAuth, SshCheck},
+	tcp::{TcpCheck, TcpScriptCheck},
+	udp::{UdpCheck, UdpReply},
+	Service, SvcMeta,
 };
-use anyhow::{Context as _, Result};
+use anyhow::{bail, Context as _, Result};
 use chrono::{DateTime, Utc};
 use rand::Rng;
+use regex::Regex;
 use serde::{
-	de::{self, Visitor},
+	de::{self, DeserializeOwned, Visitor},
 	Deserialize, Deserializer, Serialize, Serializer,
 };
 use std::{
 	collections::HashMap,
 	fmt,
-	net::{Ipv4Addr, Ipv6Addr, SocketAddrV4},
+	net::{Ipv4Addr, SocketAddrV4},
 	path::PathBuf,
 	str::FromStr,
 	sync::Arc,
 	time::Duration,
 };
 use tokio::sync::Mutex;
+use trust_dns_resolver::config::Protocol;
 
 const FIXME: &str = "172.30";
 
-fn get_sock_addr(team: &Team, vm: &Vm, port: u16) -> Result<SocketAddrV4> {
+pub fn get_sock_addr(team: &Team, vm: &Vm, port: u16) -> Result<SocketAddrV4> {
 	let host = format!("{}.{}.{}", FIXME, team.subnet, vm.host);
 
 	Ok(SocketAddrV4::new(
@@ -37,66 +57,442 @@ fn get_sock_addr(team: &Team, vm: &Vm, port: u16) -> Result<SocketAddrV4> {
 	))
 }
 
+// The mail services are built outside of `from_config` so the round-trip check
+// can reuse them for its halves.
+fn smtp_check(ty: &ServiceConfigTy, team: &Team, vm: &Vm) -> Result<SmtpCheck> {
+	match *ty {
+		ServiceConfigTy::Smtp {
+			port,
+			tls,
+			starttls,
+			ref username,
+			ref password,
+			ref from,
+			ref to,
+		} => Ok(SmtpCheck {
+			remote: get_sock_addr(
+				team,
+				vm,
+				port.unwrap_or(if tls { 465 } else { 25 }),
+			)?,
+			tls,
+			starttls,
+			username: username.clone(),
+			password: password.clone(),
+			from: from.clone(),
+			to: to.clone(),
+		}),
+		_ => bail!("Mail must be sent through an smtp service"),
+	}
+}
+
+fn pop3_check(ty: &ServiceConfigTy, team: &Team, vm: &Vm) -> Result<Pop3Check> {
+	match *ty {
+		ServiceConfigTy::Pop3 {
+			port,
+			tls,
+			starttls,
+			ref username,
+			ref password,
+			ref expect,
+		} => Ok(Pop3Check {
+			remote: get_sock_addr(
+				team,
+				vm,
+				port.unwrap_or(if tls { 995 } else { 110 }),
+			)?,
+			tls,
+			starttls,
+			username: username.clone(),
+			password: password.clone(),
+			expect: expect.clone(),
+		}),
+		_ => bail!("Mail must be received through a pop3 or imap service"),
+	}
+}
+
+fn imap_check(ty: &ServiceConfigTy, team: &Team, vm: &Vm) -> Result<ImapCheck> {
+	match *ty {
+		ServiceConfigTy::Imap {
+			port,
+			tls,
+			starttls,
+			ref username,
+			ref password,
+			ref mailbox,
+			ref expect,
+		} => Ok(ImapCheck {
+			remote: get_sock_addr(
+				team,
+				vm,
+				port.unwrap_or(if tls { 993 } else { 143 }),
+			)?,
+			tls,
+			starttls,
+			username: username.clone(),
+			password: password.clone(),
+			mailbox: mailbox.clone(),
+			expect: expect.clone(),
+		}),
+		_ => bail!("Mail must be received through a pop3 or imap service"),
+	}
+}
+
+// Checks that give up by themselves get most of the poll's timeout, so their
+// own reason gets reported before the poll is cut off with a generic one.
+pub(crate) fn check_budget(timeout: Duration) -> Duration { timeout * 4 / 5 }
+
+// Constructor for every built-in service type, see `CheckRegistry`.
+pub(crate) fn builtin_check(
+	svc: &ServiceConfig,
+	(team_id, team_meta): (&String, &Team),
+	(vm_id, vm_meta): (&String, &Vm),
+	timeout: Duration,
+) -> Result<Box<dyn Service>> {
+	let ty = svc.parse::<ServiceConfigTy>()?;
+	// If there was a way to do this without cloning everywhere, I'd be open
+	// to suggestions...
+	let inner: Box<dyn Service> = match ty {
+		ServiceConfigTy::Tcp { port } => Box::new(TcpCheck {
+			remote: get_sock_addr(team_meta, vm_meta, port)?,
+		}),
+		ServiceConfigTy::Ssh {
+			port,
+			ref username,
+			ref password,
+			ref key,
+			ref passphrase,
+			ref command,
+			ref expect,
+			ref host_key,
+		} => Box::new(SshCheck {
+			remote: get_sock_addr(team_meta, vm_meta, port.unwrap_or(22))?,
+			username: username.clone(),
+			auth: match (key, password) {
+				(Some(path), _) => SshAuth::Key {
+					path: path.clone(),
+					passphrase: passphrase.clone(),
+				},
+				(None, Some(password)) => SshAuth::Password(password.clone()),
+				(None, None) => bail!(
+					"SSH service {} needs either a password or a key",
+					svc.id
+				),
+			},
+			command: command.clone(),
+			expect: expect.clone(),
+			host_key: host_key.clone(),
+			timeout: check_budget(timeout),
+		}),
+		ServiceConfigTy::Mysql(ref sql) => Box::new(SqlCheck {
+			remote: get_sock_addr(
+				team_meta,
+				vm_meta,
+				sql.port.unwrap_or(3306),
+			)?,
+			driver: SqlDriver::Mysql,
+			login: sql.login(),
+			database: sql.database.clone(),
+			query: sql.query.clone(),
+			expect: sql.expect.clone(),
+		}),
+		ServiceConfigTy::Postgres(ref sql) => Box::new(SqlCheck {
+			remote: get_sock_addr(
+				team_meta,
+				vm_meta,
+				sql.port.unwrap_or(5432),
+			)?,
+			driver: SqlDriver::Postgres,
+			login: sql.login(),
+			database: sql.database.clone(),
+			query: sql.query.clone(),
+			expect: sql.expect.clone(),
+		}),
+		ServiceConfigTy::Ldap {
+			port,
+			tls,
+			starttls,
+			ref bind_dn,
+			ref password,
+			ref base_dn,
+			ref filter,
+			min_entries,
+			ref attribute,
+			ref expect,
+		} => Box::new(LdapCheck {
+			remote: get_sock_addr(
+				team_meta,
+				vm_meta,
+				port.unwrap_or(if tls { 636 } else { 389 }),
+			)?,
+			tls,
+			starttls,
+			bind_dn: bind_dn.clone(),
+			password: password.clone(),
+			base_dn: base_dn.clone(),
+			filter: filter.clone(),
+			min_entries,
+			attribute: match (attribute, expect) {
+				(Some(name), Some(expect)) => {
+					Some((name.clone(), expect.clone()))
+				}
+				(None, None) => None,
+				_ => bail!(
+					"Service {} needs both an attribute and an expected value",
+					svc.id
+				),
+			},
+		}),
+		ServiceConfigTy::Udp {
+			port,
+			bind_port,
+			ref payload,
+			ref expect,
+		} => Box::new(UdpCheck {
+			remote: get_sock_addr(team_meta, vm_meta, port)?,
+			// port 0 lets the OS pick
+			socket_addr: SocketAddrV4::new(
+				Ipv4Addr::new(0, 0, 0, 0),
+				bind_port.unwrap_or(0),
+			),
+			payload: payload
+				.as_ref()
+				.map(UdpPayload::to_bytes)
+				.transpose()
+				.with_context(|| {
+					format!("Invalid payload for service {}", svc.id)
+				})?,
+			expect: expect
+				.as_ref()
+				.map(UdpExpect::to_reply)
+				.transpose()
+				.with_context(|| {
+					format!("Invalid expected reply for service {}", svc.id)
+				})?,
+		}),
+		ServiceConfigTy::TcpScript {
+			port,
+			tls,
+			ref steps,
+			step_timeout,
+		} => {
+			let poll_timeout = timeout.as_millis() as u64;
+			let step_timeout = step_timeout.unwrap_or(poll_timeout / 2);
+			// anything longer and the poll's own timeout always fires first,
+			// hiding which step the service got stuck on
+			if step_timeout >= poll_timeout {
+				bail!(
+					"step_timeout of {}ms must be shorter than the {}ms check \
+					 timeout",
+					step_timeout,
+					poll_timeout
+				)
+			}
+			Box::new(TcpScriptCheck {
+				remote: get_sock_addr(team_meta, vm_meta, port)?,
+				tls,
+				steps: steps.clone(),
+				step_timeout: Duration::from_millis(step_timeout),
+			})
+		}
+		ServiceConfigTy::Exec {
+			ref program,
+			ref args,
+			port,
+		} => Box::new(ExecCheck {
+			program: program.clone(),
+			args: args.clone(),
+			team_id: team_id.clone(),
+			vm_id: vm_id.clone(),
+			ip: *get_sock_addr(team_meta, vm_meta, 0)?.ip(),
+			port,
+		}),
+		ServiceConfigTy::Script { ref path, port } => Box::new(ScriptCheck {
+			path: path.clone(),
+			team_id: team_id.clone(),
+			vm_id: vm_id.clone(),
+			ip: *get_sock_addr(team_meta, vm_meta, 0)?.ip(),
+			port,
+			timeout: check_budget(timeout),
+		}),
+		ServiceConfigTy::Plugin { ref path, port } => Box::new(
+			PluginCheck::load(
+				path.clone(),
+				*get_sock_addr(team_meta, vm_meta, 0)?.ip(),
+				port,
+				check_budget(timeout),
+			)
+			.with_context(|| {
+				format!("Failed to load plugin {}", path.display())
+			})?,
+		),
+		ServiceConfigTy::Icmp { count, max_loss } => {
+			if count == 0 || max_loss > 100 {
+				bail!(
+					"ICMP checks need a count of at least 1 and a max_loss of \
+					 at most 100%, not {} and {}%",
+					count,
+					max_loss
+				)
+			}
+			Box::new(IcmpCheck {
+				// ICMP has no ports, only the address matters
+				remote: get_sock_addr(team_meta, vm_meta, 0)?,
+				count,
+				max_loss,
+				timeout: check_budget(timeout),
+			})
+		}
+		ServiceConfigTy::Http { port, ref options } => Box::new(HttpCheck {
+			remote: get_sock_addr(team_meta, vm_meta, port.unwrap_or(80))?,
+			tls: None,
+			options: options.clone(),
+		}),
+		ServiceConfigTy::Https {
+			port,
+			ref options,
+			ref certificate,
+		} => Box::new(HttpCheck {
+			remote: get_sock_addr(team_meta, vm_meta, port.unwrap_or(443))?,
+			tls: Some(certificate.clone()),
+			options: options.clone(),
+		}),
+		ServiceConfigTy::HttpScenario {
+			port,
+			tls,
+			ref certificate,
+			ref steps,
+		} => Box::new(HttpScenarioCheck {
+			remote: get_sock_addr(
+				team_meta,
+				vm_meta,
+				port.unwrap_or(if tls { 443 } else { 80 }),
+			)?,
+			tls: if tls { Some(certificate.clone()) } else { None },
+			steps: steps.clone(),
+		}),
+		ServiceConfigTy::Dns {
+			port,
+			protocol,
+			ref name,
+			record_type,
+			matching,
+			ref values,
+		} => Box::new(DnsCheck {
+			server: get_sock_addr(team_meta, vm_meta, port.unwrap_or(53))?,
+			protocol: match protocol {
+				Protocol::Udp | Protocol::Tcp => protocol,
+				_ => bail!(
+					"DNS service {} must be queried over UDP or TCP",
+					svc.id
+				),
+			},
+			name: name.clone(),
+			record_type,
+			matching,
+			expected: match matching {
+				DnsMatch::Exists => Vec::new(),
+				_ if values.is_empty() => bail!(
+					"DNS service {} needs at least one value to match",
+					svc.id
+				),
+				_ => values
+					.iter()
+					.map(|v| DnsRecord::parse(record_type, v))
+					.collect::<Result<Vec<_>>>()?,
+			},
+		}),
+		ServiceConfigTy::Ftp {
+			port,
+			ref username,
+			ref password,
+			ref path,
+			starttls,
+			ref content_hash,
+		} => Box::new(FtpCheck {
+			remote: get_sock_addr(team_meta, vm_meta, port.unwrap_or(21))?,
+			username: username.clone(),
+			password: password.clone(),
+			path: path.clone(),
+			starttls,
+			content_hash: content_hash.clone(),
+		}),
+		ServiceConfigTy::Smb {
+			port,
+			ref username,
+			ref password,
+			ref domain,
+			ref share,
+			ref path,
+			ref content_hash,
+		} => {
+			// both caught here rather than on every poll
+			smb::check_smbclient()?;
+			smb::get_command(path)?;
+			Box::new(SmbCheck {
+				remote: get_sock_addr(team_meta, vm_meta, port.unwrap_or(445))?,
+				username: username.clone(),
+				password: password.clone(),
+				domain: domain.clone(),
+				share: share.clone(),
+				path: path.clone(),
+				content_hash: content_hash.clone(),
+			})
+		}
+		ServiceConfigTy::Smtp { .. } => {
+			Box::new(smtp_check(&ty, team_meta, vm_meta)?)
+		}
+		ServiceConfigTy::Pop3 { .. } => {
+			Box::new(pop3_check(&ty, team_meta, vm_meta)?)
+		}
+		ServiceConfigTy::Imap { .. } => {
+			Box::new(imap_check(&ty, team_meta, vm_meta)?)
+		}
+		ServiceConfigTy::Mail {
+			ref send,
+			ref receive,
+			poll_interval,
+		} => Box::new(MailCheck {
+			send: smtp_check(send, team_meta, vm_meta)?,
+			receive: match **receive {
+				ServiceConfigTy::Pop3 { .. } => {
+					Mailbox::Pop3(pop3_check(receive, team_meta, vm_meta)?)
+				}
+				_ => Mailbox::Imap(imap_check(receive, team_meta, vm_meta)?),
+			},
+			poll_interval: Duration::from_millis(poll_interval),
+			timeout: check_budget(timeout),
+		}),
+	};
+	Ok(inner)
+}
+
 #[derive(Debug)]
 pub struct SharedService {
 	pub inner: Box<dyn Service>,
 	pub meta: Arc<SvcMeta>,
+	pub depends_on: Option<String>,
+	// index of `depends_on` in the service list, see `link_dependencies`
+	pub dependency: Option<usize>,
 }
 
 impl SharedService {
 	pub fn from_config(
+		registry: &CheckRegistry,
 		svc: &ServiceConfig,
-		(team_id, team_meta): (&String, &Team),
-		(vm_id, vm_meta): (&String, &Vm),
+		team: (&String, &Team),
+		vm: (&String, &Vm),
+		timeout: Duration,
 	) -> Result<Self> {
-		// If there was a way to do this without cloning everywhere, I'd be open
-		// to suggestions...
-		let inner: Box<dyn Service> = match svc.ty {
-			ServiceConfigTy::Tcp { port } => Box::new(TcpCheck {
-				remote: get_sock_addr(team_meta, vm_meta, port)?,
-			}),
-			ServiceConfigTy::Ssh { port } => Box::new(TcpCheck {
-				remote: get_sock_addr(team_meta, vm_meta, port.unwrap_or(22))?,
-			}),
-			ServiceConfigTy::Udp { port, bind_port } => Box::new(UdpCheck {
-				remote: get_sock_addr(team_meta, vm_meta, port)?,
-				socket_addr: SocketAddrV4::new(
-					Ipv4Addr::new(0, 0, 0, 0),
-					bind_port,
-				),
-			}),
-			ServiceConfigTy::Http {
-				port,
-				ref method,
-				ref content_hash,
-			} => Box::new(HttpCheck {
-				remote: get_sock_addr(team_meta, vm_meta, port.unwrap_or(80))?,
-				method: method.0.clone(),
-				ssl: false,
-				content_hash: content_hash.clone(),
-			}),
-			ServiceConfigTy::Https {
-				port,
-				ref method,
-				ref content_hash,
-			} => Box::new(HttpCheck {
-				remote: get_sock_addr(team_meta, vm_meta, port.unwrap_or(443))?,
-				method: method.0.clone(),
-				ssl: true,
-				content_hash: content_hash.clone(),
-			}),
-			ServiceConfigTy::Dns { name, record } => {
-				Box::new(DnsCheck { name, record })
-			}
-		};
-
 		Ok(Self {
-			inner,
+			inner: registry.build(svc, team, vm, timeout)?,
 			meta: Arc::new(SvcMeta {
-				team_id: team_id.clone(),
-				vm_id: vm_id.clone(),
+				team_id: team.0.clone(),
+				vm_id: vm.0.clone(),
 				svc_id: svc.id.clone(),
 			}),
+			depends_on: svc.depends_on.clone(),
+			dependency: None,
 		})
 	}
 }
@@ -145,6 +541,8 @@ pub struct Cfg {
 	pub patch_server: PathBuf,
 	#[serde(skip)]
 	pub _services: Mutex<Vec<SharedService>>,
+	#[serde(skip)]
+	pub registry: CheckRegistry,
 	pub database: String,
 	pub web: Web,
 }
@@ -155,11 +553,18 @@ impl Cfg {
 		for vm in self.boxes.iter() {
 			for team in self.teams.iter() {
 				for svc in vm.1.services.iter() {
-					__services.push(SharedService::from_config(svc, team, vm)?);
+					__services.push(SharedService::from_config(
+						&self.registry,
+						svc,
+						team,
+						vm,
+						self.checks.get_timeout(),
+					)?);
 				}
 			}
 		}
 
+		link_dependencies(&mut __services)?;
 		self._services = Mutex::new(__services);
 		Ok(self)
 	}
@@ -172,6 +577,15 @@ pub struct Team {
 	pub timeout: u8,
 	pub subnet: u8,
 	pub password: String,
+	// pools of accounts on the team's boxes, by name
+	#[serde(default)]
+	pub credentials: HashMap<String, Vec<Credential>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Credential {
+	pub username: String,
+	pub password: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -202,6 +616,11 @@ pub struct CheckSettings {
 }
 
 impl CheckSettings {
+	// every poll is cut off after this long
+	pub fn get_timeout(&self) -> Duration {
+		Duration::from_secs(self.timeout as u64)
+	}
+
 	pub fn get_interval(&self) -> Duration {
 		Duration::from_secs(
 			(self.interval as i32
@@ -217,51 +636,465 @@ pub struct Vm {
 	pub services: Vec<ServiceConfig>,
 }
 
+pub type ServiceTable = serde_json::Map<String, serde_json::Value>;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ServiceConfig {
 	pub id: String,
+	#[serde(rename = "type")]
+	pub ty: String,
+	// id of another service on the same box that has to be up for this one
+	// to be checked. Dependents only start once their dependency is done, so
+	// a chain of n services can take up to n check timeouts each round.
+	#[serde(rename = "dependsOn")]
+	pub depends_on: Option<String>,
+	// name of a pool in each team's `credentials` to log in with
+	pub credentials: Option<String>,
+	// the rest of the service's table, left for its check to make sense of
 	#[serde(flatten)]
-	pub ty: ServiceConfigTy,
+	pub table: ServiceTable,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum CompositeMode {
+	All,
+	Any,
+	AtLeast(usize),
+}
+
+impl Default for CompositeMode {
+	fn default() -> Self { CompositeMode::All }
+}
+
+// a `composite` service, up depending on how many of `checks` are
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CompositeConfig {
+	#[serde(default)]
+	pub mode: CompositeMode,
+	pub checks: Vec<ServiceConfig>,
+}
+
+impl ServiceConfig {
+	// deserializes the table, `type` included, into a check's own config
+	pub fn parse<T: DeserializeOwned>(&self) -> Result<T> {
+		let mut table = self.table.clone();
+		table.insert("type".into(), self.ty.clone().into());
+		serde_json::from_value(table.into())
+			.with_context(|| format!("Invalid config for service {}", self.id))
+	}
 }
 
 #[derive(Debug, Clone)]
 pub struct HttpMethod(pub reqwest::Method);
 
+fn default_http_path() -> String { "/".into() }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum UdpPayload {
+	Hex(String),
+	Text(String),
+}
+
+// whitespace is allowed to break up long payloads
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+	Ok(hex::decode(hex.split_whitespace().collect::<String>())?)
+}
+
+impl UdpPayload {
+	pub fn to_bytes(&self) -> Result<Vec<u8>> {
+		match self {
+			UdpPayload::Hex(hex) => decode_hex(hex),
+			UdpPayload::Text(text) => Ok(text.as_bytes().to_vec()),
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum UdpExpect {
+	Hex(String),
+	Text(String),
+	Regex(Pattern),
+}
+
+impl UdpExpect {
+	pub fn to_reply(&self) -> Result<UdpReply> {
+		match self {
+			UdpExpect::Hex(hex) => Ok(UdpReply::Bytes(decode_hex(hex)?)),
+			UdpExpect::Text(text) => {
+				Ok(UdpReply::Bytes(text.as_bytes().to_vec()))
+			}
+			UdpExpect::Regex(re) => Ok(UdpReply::Regex(re.0.clone())),
+		}
+	}
+}
+
+// one step of a `tcpScript` service
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptStep {
+	Send(String),
+	Expect(Pattern),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HttpOptions {
+	pub method: HttpMethod,
+	// path and query, e.g. `/index.php?page=about`
+	#[serde(default = "default_http_path")]
+	pub path: String,
+	// sent as the Host header to pick a virtual host
+	pub host: Option<String>,
+	#[serde(default)]
+	pub headers: HashMap<String, String>,
+	pub body: Option<String>,
+	// any 2xx is accepted if empty
+	#[serde(default)]
+	pub status: Vec<u16>,
+	pub content_hash: Option<String>,
+	#[serde(default)]
+	pub expect: Vec<OutputMatch>,
+	#[serde(default)]
+	pub response_headers: HashMap<String, OutputMatch>,
+}
+
+// A service's `certificate` table. Every field left unset is skipped, so the
+// default accepts any certificate.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TlsPolicy {
+	// PEM bundle the certificate must chain to
+	pub ca: Option<PathBuf>,
+	// name that must appear in the certificate's SAN or CN
+	pub hostname: Option<String>,
+	// fail once the certificate is this close to expiring
+	pub expiry_days: Option<u32>,
+	// SHA-256 of the leaf certificate, hex encoded
+	pub fingerprint: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HttpStep {
+	#[serde(flatten)]
+	pub request: HttpOptions,
+	// sent urlencoded as the request body, overriding `body`
+	#[serde(default)]
+	pub form: HashMap<String, String>,
+	// variables for later steps, taken from the first capture group
+	#[serde(default)]
+	pub extract: HashMap<String, Pattern>,
+}
+
+// Per-team logins come from a credential pool, which fills in `username` and
+// `password` for each team.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SqlConfig {
+	pub port: Option<u16>,
+	pub username: String,
+	pub password: String,
+	pub database: Option<String>,
+	pub query: String,
+	// compared against the first column of the first row
+	pub expect: Option<OutputMatch>,
+}
+
+impl SqlConfig {
+	fn login(&self) -> Credential {
+		Credential {
+			username: self.username.clone(),
+			password: self.password.clone(),
+		}
+	}
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum ServiceConfigTy {
 	Tcp {
 		port: u16,
 	},
+	TcpScript {
+		port: u16,
+		#[serde(default)]
+		tls: bool,
+		steps: Vec<ScriptStep>,
+		// milliseconds each expect waits for a matching line, half the team's
+		// timeout if unset
+		step_timeout: Option<u64>,
+	},
 	Udp {
 		port: u16,
-		bind_port: u16,
+		bind_port: Option<u16>,
+		// sent as a single datagram, empty if unset but `expect` is set; with
+		// neither, nothing is sent at all
+		payload: Option<UdpPayload>,
+		expect: Option<UdpExpect>,
 	},
 	Ssh {
 		port: Option<u16>,
+		username: String,
+		password: Option<String>,
+		key: Option<PathBuf>,
+		passphrase: Option<String>,
+		command: String,
+		expect: Option<OutputMatch>,
+		host_key: Option<String>,
 	},
 	Http {
 		port: Option<u16>,
-		method: HttpMethod,
-		content_hash: Option<String>,
+		#[serde(flatten)]
+		options: HttpOptions,
 	},
 	Https {
 		port: Option<u16>,
-		method: HttpMethod,
-		content_hash: Option<String>,
+		#[serde(flatten)]
+		options: HttpOptions,
+		#[serde(default)]
+		certificate: TlsPolicy,
+	},
+	HttpScenario {
+		port: Option<u16>,
+		#[serde(default)]
+		tls: bool,
+		// only checked with `tls` on
+		#[serde(default)]
+		certificate: TlsPolicy,
+		steps: Vec<HttpStep>,
 	},
 	Dns {
-		name: Ipv4Addr,
-		#[serde(flatten)]
-		record: DnsRecord,
+		port: Option<u16>,
+		#[serde(default = "default_dns_protocol")]
+		protocol: Protocol,
+		name: String,
+		#[serde(rename = "recordType")]
+		record_type: DnsRecordType,
+		#[serde(default, rename = "match")]
+		matching: DnsMatch,
+		#[serde(default)]
+		values: Vec<String>,
+	},
+	Ftp {
+		port: Option<u16>,
+		username: Option<String>,
+		password: Option<String>,
+		path: String,
+		// explicit FTPS, upgraded with AUTH TLS
+		#[serde(default)]
+		starttls: bool,
+		content_hash: String,
+	},
+	Smb {
+		port: Option<u16>,
+		username: String,
+		password: String,
+		domain: Option<String>,
+		share: String,
+		path: String,
+		content_hash: String,
+	},
+	Smtp {
+		port: Option<u16>,
+		#[serde(default)]
+		tls: bool,
+		#[serde(default)]
+		starttls: bool,
+		username: Option<String>,
+		password: Option<String>,
+		from: String,
+		to: String,
+	},
+	Pop3 {
+		port: Option<u16>,
+		#[serde(default)]
+		tls: bool,
+		#[serde(default)]
+		starttls: bool,
+		username: String,
+		password: String,
+		expect: Option<OutputMatch>,
+	},
+	Imap {
+		port: Option<u16>,
+		#[serde(default)]
+		tls: bool,
+		#[serde(default)]
+		starttls: bool,
+		username: String,
+		password: String,
+		#[serde(default = "default_mailbox")]
+		mailbox: String,
+		expect: Option<OutputMatch>,
 	},
+	// sends a nonce through `send` (an smtp service) and waits for it to show
+	// up in `receive` (a pop3 or imap service)
+	Mail {
+		send: Box<ServiceConfigTy>,
+		receive: Box<ServiceConfigTy>,
+		// milliseconds between mailbox polls
+		#[serde(default = "default_mail_poll_interval")]
+		poll_interval: u64,
+	},
+	Mysql(SqlConfig),
+	Postgres(SqlConfig),
+	Ldap {
+		port: Option<u16>,
+		#[serde(default)]
+		tls: bool,
+		#[serde(default)]
+		starttls: bool,
+		bind_dn: String,
+		password: String,
+		base_dn: String,
+		#[serde(default = "default_ldap_filter")]
+		filter: String,
+		#[serde(default = "default_min_entries")]
+		min_entries: usize,
+		attribute: Option<String>,
+		expect: Option<OutputMatch>,
+	},
+	Exec {
+		program: PathBuf,
+		#[serde(default)]
+		args: Vec<String>,
+		// only passed along to the program, which may not need it
+		port: Option<u16>,
+	},
+	Script {
+		path: PathBuf,
+		port: Option<u16>,
+	},
+	// a sandboxed WebAssembly check, see `checks::plugin` for the interface
+	Plugin {
+		path: PathBuf,
+		port: Option<u16>,
+	},
+	Icmp {
+		#[serde(default = "default_ping_count")]
+		count: u16,
+		// percent of echo requests that may go unanswered
+		#[serde(default)]
+		max_loss: u8,
+	},
+}
+
+fn default_dns_protocol() -> Protocol { Protocol::Udp }
+
+fn default_mailbox() -> String { "INBOX".into() }
+
+fn default_mail_poll_interval() -> u64 { 1000 }
+
+fn default_ldap_filter() -> String { "(objectClass=*)".into() }
+
+fn default_min_entries() -> usize { 1 }
+
+fn default_ping_count() -> u16 { 3 }
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsRecordType {
+	A,
+	AAAA,
+	MX,
+	CNAME,
+	TXT,
+	NS,
+	SOA,
+	PTR,
+	SRV,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum DnsMatch {
+	// at least one of `values` is in the answer
+	OneOf,
+	// every one of `values` is in the answer
+	AllOf,
+	// the server answered with any record of the type
+	Exists,
+}
+
+impl Default for DnsMatch {
+	fn default() -> Self { DnsMatch::OneOf }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(tag = "recordType", rename_all = "lowercase")]
-pub enum DnsRecord {
-	A { addr: Ipv4Addr },
-	AAAA { addr: Ipv6Addr },
+#[serde(rename_all = "camelCase")]
+pub enum OutputMatch {
+	Exact(String),
+	Contains(String),
+	Regex(Pattern),
+}
+
+impl fmt::Display for OutputMatch {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			OutputMatch::Exact(expected) => write!(f, "exactly {:?}", expected),
+			OutputMatch::Contains(expected) => {
+				write!(f, "containing {:?}", expected)
+			}
+			OutputMatch::Regex(pattern) => {
+				write!(f, "matching /{}/", pattern.0.as_str())
+			}
+		}
+	}
+}
+
+impl OutputMatch {
+	pub fn is_match(&self, output: &str) -> bool {
+		match self {
+			OutputMatch::Exact(expected) => output.trim() == expected,
+			OutputMatch::Contains(expected) => {
+				output.contains(expected.as_str())
+			}
+			OutputMatch::Regex(pattern) => pattern.0.is_match(output),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern(pub Regex);
+
+impl Serialize for Pattern {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.serialize_str(self.0.as_str())
+	}
+}
+
+struct PatternVisitor;
+impl<'de> Visitor<'de> for PatternVisitor {
+	type Value = Pattern;
+
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		write!(formatter, "a valid regular expression")
+	}
+
+	fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		Ok(Pattern(Regex::new(v).map_err(E::custom)?))
+	}
+
+	fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+	where
+		E: de::Error,
+	{
+		Ok(Pattern(Regex::new(&v).map_err(E::custom)?))
+	}
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		deserializer.deserialize_str(PatternVisitor)
+	}
 }
 
 impl Serialize for HttpMethod {
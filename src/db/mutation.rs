@@ -1,6 +1,10 @@
 use super::PgPool;
-use crate::{checks::SvcMeta, config::Cfg};
+use crate::{
+	checks::{ChanMsg, SvcMeta},
+	config::Cfg,
+};
 use anyhow::{Context, Result};
+use sqlx::{Postgres, Transaction};
 use std::sync::Arc;
 
 pub async fn setup(cfg: Arc<Cfg>, pool: PgPool) -> Result<()> {
@@ -42,14 +46,56 @@ pub async fn setup(cfg: Arc<Cfg>, pool: PgPool) -> Result<()> {
 	Ok(())
 }
 
-pub async fn persist_uptime(meta: &SvcMeta, pool: PgPool) -> Result<()> {
+pub async fn persist_result(
+	msg: &ChanMsg,
+	tx: &mut Transaction<'_, Postgres>,
+) -> Result<()> {
+	let meta = &msg.meta;
+	sqlx::query!(
+		r#"
+		INSERT INTO check_results(round, checked_at, svc_id, vm_id, team_id,
+										  status, latency_ms, error, username)
+			  VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9);
+		"#,
+		msg.round,
+		msg.checked_at,
+		&*meta.svc_id,
+		&*meta.vm_id,
+		&*meta.team_id,
+		msg.outcome.status(),
+		msg.latency.as_millis() as i32,
+		msg.outcome.reason(),
+		msg.username
+	)
+	.execute(tx)
+	.await
+	.with_context(|| {
+		format!(
+			"Failed to persist check result for box: {} - {} - {}!",
+			&*meta.svc_id, &*meta.vm_id, &*meta.team_id,
+		)
+	})
+	.map(|_| ())
+}
+
+// The check result and the service's totals are written together, so a failed
+// write can't leave a result the scores don't count.
+pub async fn persist_uptime(msg: &ChanMsg, pool: PgPool) -> Result<()> {
+	let mut tx = pool
+		.begin()
+		.await
+		.context("Failed to start persisting a check result")?;
+	persist_result(msg, &mut tx).await?;
+
+	let meta = &msg.meta;
 	sqlx::query!(
 		r#"
 		UPDATE	services
 			SET	check_count = check_count + 1,
 					uptime_score = uptime_score + 1,
 					recurring_down = 0,
-					latest_uptime_status = TRUE
+					latest_uptime_status = TRUE,
+					latest_failure_reason = NULL
 		 WHERE	svc_id = $1 AND
 					vm_id = $2 AND
 					team_id = $3;
@@ -58,30 +104,39 @@ pub async fn persist_uptime(meta: &SvcMeta, pool: PgPool) -> Result<()> {
 		&*meta.vm_id,
 		&*meta.team_id
 	)
-	.execute(&pool)
+	.execute(&mut tx)
 	.await
 	.with_context(|| {
 		format!(
 			"Failed to persist uptime for box: {} - {} - {}!",
 			&*meta.svc_id, &*meta.vm_id, &*meta.team_id,
 		)
-	})
-	.map(|_| ())
+	})?;
+
+	tx.commit().await.context("Failed to commit a check result")
 }
 
-pub async fn persist_downtime(meta: &SvcMeta, pool: PgPool) -> Result<()> {
+pub async fn persist_downtime(msg: &ChanMsg, pool: PgPool) -> Result<()> {
 	#[derive(sqlx::FromRow, Debug)]
 	struct DowntimeReturn {
 		recurring_down: i32,
 	}
 
+	let mut tx = pool
+		.begin()
+		.await
+		.context("Failed to start persisting a check result")?;
+	persist_result(msg, &mut tx).await?;
+
+	let meta = &msg.meta;
 	let service = sqlx::query_as!(
 		DowntimeReturn,
 		r#"
 		UPDATE	services
 			SET	check_count = check_count + 1,
 					recurring_down = recurring_down + 1,
-					latest_uptime_status = FALSE
+					latest_uptime_status = FALSE,
+					latest_failure_reason = $4
 		 WHERE	svc_id = $1 AND
 					vm_id = $2 AND
 					team_id = $3
@@ -89,9 +144,10 @@ pub async fn persist_downtime(meta: &SvcMeta, pool: PgPool) -> Result<()> {
 		"#,
 		&*meta.svc_id,
 		&*meta.vm_id,
-		&*meta.team_id
+		&*meta.team_id,
+		msg.outcome.reason()
 	)
-	.fetch_one(&pool)
+	.fetch_one(&mut tx)
 	.await
 	.with_context(|| {
 		format!(
@@ -101,13 +157,16 @@ pub async fn persist_downtime(meta: &SvcMeta, pool: PgPool) -> Result<()> {
 	})?;
 
 	if service.recurring_down >= 5 {
-		persist_sla(meta, pool).await?;
+		persist_sla(meta, &mut tx).await?;
 	}
 
-	Ok(())
+	tx.commit().await.context("Failed to commit a check result")
 }
 
-pub async fn persist_sla(meta: &SvcMeta, pool: PgPool) -> Result<()> {
+pub async fn persist_sla(
+	meta: &SvcMeta,
+	tx: &mut Transaction<'_, Postgres>,
+) -> Result<()> {
 	sqlx::query!(
 		r#"
 		UPDATE	services
@@ -120,7 +179,7 @@ pub async fn persist_sla(meta: &SvcMeta, pool: PgPool) -> Result<()> {
 		&*meta.vm_id,
 		&*meta.team_id
 	)
-	.execute(&pool)
+	.execute(tx)
 	.await
 	.with_context(|| {
 		format!(
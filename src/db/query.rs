@@ -1,6 +1,21 @@
+use super::PgPool;
 use crate::web::templates::{LeaderboardItem, SvcInfo, TeamInfo};
+use anyhow::{Context, Result};
 use sqlx::{pool::PoolConnection, Postgres};
 
+// rounds carry on from here after a restart, so `check_results.round` keeps
+// meaning the same round across the whole competition
+pub async fn get_last_round(pool: &PgPool) -> Result<i32> {
+	sqlx::query_scalar!(
+		r#"
+		SELECT COALESCE(MAX(round), 0) AS "round!" FROM check_results;
+		"#
+	)
+	.fetch_one(pool)
+	.await
+	.context("Failed to fetch the last scored round")
+}
+
 pub async fn get_team_info(
 	conn: &mut PoolConnection<Postgres>,
 ) -> Vec<TeamInfo> {
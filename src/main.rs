@@ -4,7 +4,7 @@ use libscylla::{
 	checks::{enter_event_loop, enter_recv_loop, injects, ChanMsg},
 	cli::{Opts, SubCommand},
 	config::Cfg,
-	db::{establish_pg_conn, mutation, PgPool},
+	db::{establish_pg_conn, mutation, query, PgPool},
 	web,
 };
 use std::{fs, sync::Arc};
@@ -27,8 +27,9 @@ async fn main() -> Result<()> {
 async fn run(cfg: Arc<Cfg>, pool: PgPool) -> Result<()> {
 	let (tx, rx) = mpsc::unbounded_channel::<ChanMsg>();
 
-	// periodically run checks
-	task::spawn(enter_event_loop(cfg.clone(), tx));
+	// periodically run checks, numbering rounds on from the last run
+	let last_round = query::get_last_round(&pool).await?;
+	task::spawn(enter_event_loop(cfg.clone(), tx, last_round));
 
 	// start web server
 	task::spawn(web::start(pool.clone(), cfg.clone()));